@@ -0,0 +1,384 @@
+//! Subcommands which change or print the state without opening the TUI,
+//! so that timetrack can be driven from scripts.
+
+mod report;
+mod status;
+
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, TimeDelta, Weekday};
+use clap::{Parser, Subcommand};
+use color_eyre::Result;
+use color_eyre::eyre::{bail, eyre};
+
+use crate::load_state;
+use crate::recommend::StrategyKind;
+use crate::schedule::{CarryOver, Schedule, parse_day_target};
+use crate::state::{
+    Activity, DeletionError, EndActivityError, PauseError, StartActivityError, State, TodoItem,
+};
+use report::ReportFormat;
+use status::StatusFormat;
+
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about = "Track daily activities, pomodoro sessions and todo lists"
+)]
+pub struct Cli {
+    /// Opens the TUI when no subcommand is given
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Starts a session for an activity
+    Start { activity: String },
+    /// Ends the ongoing session
+    Stop,
+    /// Stops counting time towards the ongoing session until it is resumed
+    Pause,
+    /// Starts counting time towards the paused session again
+    Resume,
+    /// Prints the ongoing session, without writing anything
+    Status {
+        #[arg(long, value_enum, default_value_t = StatusFormat::Plain)]
+        format: StatusFormat,
+    },
+    /// Sums up the time spent on each activity over a range of days, without writing anything
+    Report {
+        /// The first day of the report, which defaults to six days before the last one
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// The last day of the report, which defaults to today
+        #[arg(long)]
+        to: Option<NaiveDate>,
+        #[arg(long, value_enum, default_value_t = ReportFormat::Md)]
+        format: ReportFormat,
+        /// Writes the report to a file instead of printing it
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Adds an activity with a daily target
+    AddActivity { name: String, minutes: usize },
+    /// Removes an activity
+    DeleteActivity { activity: String },
+    /// Adds time to an activity, for work done without a session
+    Log { activity: String, minutes: usize },
+    /// Lists the activities and the time spent on each of them today
+    Activities,
+    /// Changes which days an activity has a target on
+    Schedule {
+        activity: String,
+        #[command(subcommand)]
+        schedule: ScheduleCommand,
+    },
+    /// Carries the time an activity falls short of its target into the next day's target
+    CarryOver {
+        activity: String,
+        /// Stops carrying time over, and forgets the balance
+        #[arg(long, conflicts_with = "surplus")]
+        off: bool,
+        /// Also carries time worked beyond the target, which lowers the next day's target
+        #[arg(long)]
+        surplus: bool,
+        /// The most the balance can reach, in minutes
+        #[arg(long, default_value_t = 120)]
+        cap: usize,
+        /// The percentage of the balance which is forgiven every day
+        #[arg(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+        decay: u8,
+    },
+    /// Sets how an activity ranks with the priority strategy, where higher comes first
+    Priority { activity: String, priority: usize },
+    /// Prints or changes how the activity to work on next is picked
+    Strategy {
+        #[arg(value_enum)]
+        strategy: Option<StrategyKind>,
+    },
+    /// Adds to or lists the todo list
+    #[command(subcommand)]
+    Todo(TodoCommand),
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ScheduleCommand {
+    /// The same target every day
+    Daily { minutes: usize },
+    /// Targets on some days of the week, given like `mon=60 wed=60 fri=60`, with the other days off
+    Days {
+        #[arg(required = true, value_parser = parse_day_target)]
+        targets: Vec<(Weekday, usize)>,
+    },
+    /// A target for the whole week, spread evenly over the days left in it
+    Weekly { minutes: usize },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TodoCommand {
+    /// Adds a todo to a bucket
+    Add { bucket: String, text: String },
+    /// Lists every bucket and its todos
+    List,
+}
+
+pub fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Status { format } => status::print_status(&load_for_reading()?, format),
+        Command::Activities => print_activities(&load_for_reading()?),
+        Command::Report {
+            from,
+            to,
+            format,
+            output,
+        } => {
+            let state = load_for_reading()?;
+            let to = to.unwrap_or(state.date());
+            let from = from.unwrap_or(to - TimeDelta::days(6));
+            if from > to {
+                bail!("the report would start on {from}, after it ends on {to}");
+            }
+            report::write_report(&state, from, to, format, output)
+        }
+        Command::Todo(TodoCommand::List) => print_todos(&load_for_reading()?),
+        Command::Strategy { strategy: None } => {
+            println!("{}", load_for_reading()?.recommendation_strategy().name());
+            Ok(())
+        }
+        command => {
+            let (mut state, state_lock) = load_state(false)?;
+            if state_lock.is_none() {
+                bail!(
+                    "another instance of timetrack is using the state file, so it can't be changed"
+                );
+            }
+            if state.date() != state.today() {
+                state.refresh();
+            }
+            change_state(&mut state, command)?;
+            state.save()
+        }
+    }
+}
+
+/// Loads the state without writing anything, not even the lock file or a backup
+fn load_for_reading() -> Result<State> {
+    let (mut state, _) = load_state(true)?;
+    if state.date() != state.today() {
+        // this is never saved, but it stops yesterday's time from being shown as today's
+        state.refresh();
+    }
+    Ok(state)
+}
+
+fn change_state(state: &mut State, command: Command) -> Result<()> {
+    match command {
+        Command::Start { activity } => {
+            let activity = find_activity(state, &activity)?;
+            let (id, name) = (activity.id(), activity.name().to_string());
+            match state.start_activity(id) {
+                Ok(()) => println!("Started {name}"),
+                Err(StartActivityError::AlreadyOngoing) => bail!(
+                    "{} is already ongoing",
+                    state
+                        .current_activity()
+                        .map_or("another activity", Activity::name)
+                ),
+                Err(StartActivityError::InvalidId) => bail!("no activity is named \"{name}\""),
+            }
+        }
+        Command::Stop => {
+            let name = state.current_activity().map(|x| x.name().to_string());
+            let minutes = state.current_task_minutes();
+            match state.end_activity(true) {
+                Ok(()) => println!(
+                    "Stopped {} after {}min",
+                    name.unwrap_or_default(),
+                    minutes.unwrap_or(0)
+                ),
+                Err(EndActivityError::NoCurrentActivity) => bail!("no activity is ongoing"),
+                Err(EndActivityError::PomoOngoing) => unreachable!("the pomodoro was overridden"),
+            }
+        }
+        Command::Pause => {
+            let name = state.current_activity().map(|x| x.name().to_string());
+            match state.pause() {
+                Ok(()) => println!("Paused {}", name.unwrap_or_default()),
+                Err(PauseError::NoCurrentActivity) => bail!("no activity is ongoing"),
+                Err(PauseError::AlreadyPaused) => bail!("the ongoing session is already paused"),
+                Err(PauseError::NotPaused) => unreachable!("pausing never checks for a pause"),
+            }
+        }
+        Command::Resume => {
+            let name = state.current_activity().map(|x| x.name().to_string());
+            match state.resume() {
+                Ok(()) => println!("Resumed {}", name.unwrap_or_default()),
+                Err(PauseError::NoCurrentActivity) => bail!("no activity is ongoing"),
+                Err(PauseError::NotPaused) => bail!("the ongoing session isn't paused"),
+                Err(PauseError::AlreadyPaused) => unreachable!("resuming never checks for a pause"),
+            }
+        }
+        Command::AddActivity { name, minutes } => {
+            if state.activities().any(|x| x.name() == name) {
+                bail!("there is already an activity named \"{name}\"");
+            }
+            state.add_activity(name.clone(), minutes);
+            println!("Added {name} with a target of {minutes}min");
+        }
+        Command::DeleteActivity { activity } => {
+            let activity = find_activity(state, &activity)?;
+            let (id, name) = (activity.id(), activity.name().to_string());
+            match state.delete(id) {
+                Ok(()) => println!("Deleted {name}"),
+                Err(DeletionError::PomoOngoing) => {
+                    bail!("{name} has a pomodoro session ongoing, so it can't be deleted")
+                }
+                Err(DeletionError::InvalidId) => bail!("no activity is named \"{name}\""),
+            }
+        }
+        Command::Log { activity, minutes } => {
+            let activity = find_activity(state, &activity)?;
+            let (id, name) = (activity.id(), activity.name().to_string());
+            state
+                .add_time(id, minutes)
+                .map_err(|()| eyre!("no activity is named \"{name}\""))?;
+            println!("Logged {minutes}min for {name}");
+        }
+        Command::Schedule { activity, schedule } => {
+            let activity = find_activity(state, &activity)?;
+            let (id, name) = (activity.id(), activity.name().to_string());
+            let result = match schedule {
+                ScheduleCommand::Daily { minutes } => state.set_daily_target(id, minutes),
+                ScheduleCommand::Days { targets } => {
+                    let mut minutes = [0; 7];
+                    for (day, target) in targets {
+                        minutes[day.num_days_from_monday() as usize] = target;
+                    }
+                    state.set_schedule(id, Schedule::Weekdays(minutes))
+                }
+                ScheduleCommand::Weekly { minutes } => {
+                    state.set_schedule(id, Schedule::Weekly(minutes))
+                }
+            };
+            result.map_err(|()| eyre!("no activity is named \"{name}\""))?;
+            let activity = state.activity_by_id(id).unwrap();
+            println!(
+                "Scheduled {name} {}, with a target of {}min today",
+                activity.schedule(),
+                activity.today_target_minutes()
+            );
+        }
+        Command::CarryOver {
+            activity,
+            off,
+            surplus,
+            cap,
+            decay,
+        } => {
+            let activity = find_activity(state, &activity)?;
+            let (id, name) = (activity.id(), activity.name().to_string());
+            let carry_over = (!off).then_some(CarryOver {
+                surplus,
+                cap_minutes: cap,
+                decay_percent: decay,
+            });
+            state
+                .set_carry_over(id, carry_over)
+                .map_err(|()| eyre!("no activity is named \"{name}\""))?;
+            if off {
+                println!("Stopped carrying time over for {name}");
+            } else {
+                println!("Carrying up to {cap}min over for {name}");
+            }
+        }
+        Command::Priority { activity, priority } => {
+            let activity = find_activity(state, &activity)?;
+            let (id, name) = (activity.id(), activity.name().to_string());
+            state
+                .set_priority(id, priority)
+                .map_err(|()| eyre!("no activity is named \"{name}\""))?;
+            println!("Set the priority of {name} to {priority}");
+        }
+        Command::Strategy {
+            strategy: Some(strategy),
+        } => {
+            state.set_recommendation_strategy(strategy);
+            println!("Recommending activities by {}", strategy.name());
+        }
+        Command::Todo(TodoCommand::Add { bucket, text }) => {
+            let bucket_index = state
+                .get_buckets()
+                .position(|x| x.name() == bucket)
+                .ok_or_else(|| eyre!("no bucket is named \"{bucket}\""))?;
+            state
+                .add_todo(bucket_index, TodoItem::new(text.clone()))
+                .map_err(|()| eyre!("no bucket is named \"{bucket}\""))?;
+            println!("Added \"{text}\" to {bucket}");
+        }
+        Command::Status { .. }
+        | Command::Report { .. }
+        | Command::Activities
+        | Command::Strategy { strategy: None }
+        | Command::Todo(TodoCommand::List) => {
+            unreachable!("commands which only print the state don't change it")
+        }
+    }
+    Ok(())
+}
+
+/// Finds an activity by its name, ignoring case if there is no exact match
+fn find_activity<'a>(state: &'a State, name: &str) -> Result<&'a Activity> {
+    state
+        .activities()
+        .find(|x| x.name() == name)
+        .or_else(|| {
+            state
+                .activities()
+                .find(|x| x.name().to_lowercase() == name.to_lowercase())
+        })
+        .ok_or_else(|| eyre!("no activity is named \"{name}\""))
+}
+
+fn print_activities(state: &State) -> Result<()> {
+    for activity in state.activities() {
+        let acheived = state.acheived_minutes(activity.id())
+            + if state.current_id() == Some(activity.id()) {
+                state.current_task_minutes().unwrap_or(0)
+            } else {
+                0
+            };
+        let mut notes = vec![];
+        if !activity.schedule().is_daily() {
+            notes.push(activity.schedule().to_string());
+        }
+        if activity.carry_over().is_some() {
+            notes.push(format!("balance {:+}min", activity.balance()));
+        }
+        if notes.is_empty() {
+            println!(
+                "{}\t{acheived} / {}min",
+                activity.name(),
+                activity.today_target_minutes()
+            );
+        } else {
+            println!(
+                "{}\t{acheived} / {}min\t{}",
+                activity.name(),
+                activity.today_target_minutes(),
+                notes.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn print_todos(state: &State) -> Result<()> {
+    for bucket in state.get_buckets() {
+        println!("{}", bucket.name());
+        for todo in bucket.todos() {
+            println!("  - {}", todo.item());
+        }
+    }
+    Ok(())
+}
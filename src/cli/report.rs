@@ -0,0 +1,383 @@
+//! `timetrack report`, which sums up where time went over a range of days, for sharing outside timetrack
+
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use color_eyre::Result;
+
+use crate::state::{ActivityId, DayRecord, LogEntry, State};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    /// A Markdown table
+    Md,
+    /// A standalone HTML page with a table
+    Html,
+    /// A header row, then a row of minutes for each activity
+    Csv,
+}
+
+/// The time spent on an activity over the days of a report
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct ActivityReport {
+    id: Option<ActivityId>,
+    /// The most recent name of the activity
+    name: String,
+    minutes: usize,
+    target_minutes: usize,
+    days_met: usize,
+    /// The days which had a target, which excludes days off
+    days_with_target: usize,
+    /// Pomodoro sessions which ran until their timer was up
+    pomodoros: usize,
+    longest_session_minutes: usize,
+}
+impl ActivityReport {
+    fn add_day(&mut self, acheived_minutes: usize, target_minutes: usize) {
+        self.minutes += acheived_minutes;
+        self.target_minutes += target_minutes;
+        if target_minutes > 0 {
+            self.days_with_target += 1;
+            if acheived_minutes >= target_minutes {
+                self.days_met += 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Report {
+    from: NaiveDate,
+    to: NaiveDate,
+    /// How many days in the range timetrack has a record of
+    days: usize,
+    activities: Vec<ActivityReport>,
+}
+impl Report {
+    /// Sums up `days`, which should be in order and within `from` to `to`
+    fn new<'a>(
+        from: NaiveDate,
+        to: NaiveDate,
+        days: impl IntoIterator<Item = &'a DayRecord>,
+    ) -> Self {
+        let mut report = Report {
+            from,
+            to,
+            days: 0,
+            activities: vec![],
+        };
+        for day in days {
+            report.days += 1;
+            for archived in &day.activities {
+                let activity = report.activity(archived.id);
+                activity.name = archived.name.clone();
+                activity.add_day(archived.acheived_minutes, archived.target_minutes);
+            }
+            for entry in &day.log {
+                let LogEntry::Session(session) = entry else {
+                    continue;
+                };
+                let activity = report.activity(session.activity_id);
+                // the rest of a split session is logged on the next day, where it is counted instead
+                if session.pomo_minutes.is_some() && !session.overridden && !session.split {
+                    activity.pomodoros += 1;
+                }
+                activity.longest_session_minutes =
+                    activity.longest_session_minutes.max(session.minutes());
+            }
+        }
+        // sessions of activities which were deleted before the end of their day have no name
+        report
+            .activities
+            .retain(|activity| !activity.name.is_empty());
+        report
+    }
+
+    fn activity(&mut self, id: ActivityId) -> &mut ActivityReport {
+        match self.activities.iter().position(|x| x.id == Some(id)) {
+            Some(position) => &mut self.activities[position],
+            None => {
+                self.activities.push(ActivityReport {
+                    id: Some(id),
+                    ..Default::default()
+                });
+                self.activities.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Every activity added together
+    fn total(&self) -> ActivityReport {
+        let mut total = ActivityReport {
+            name: "Total".to_string(),
+            ..Default::default()
+        };
+        for activity in &self.activities {
+            total.minutes += activity.minutes;
+            total.target_minutes += activity.target_minutes;
+            total.days_met += activity.days_met;
+            total.days_with_target += activity.days_with_target;
+            total.pomodoros += activity.pomodoros;
+            total.longest_session_minutes = total
+                .longest_session_minutes
+                .max(activity.longest_session_minutes);
+        }
+        total
+    }
+
+    fn markdown(&self) -> String {
+        let mut markdown = format!(
+            "# Time Report: {} to {}\n\n{} in total over {} tracked days.\n\n",
+            self.from,
+            self.to,
+            format_minutes(self.total().minutes),
+            self.days
+        );
+        markdown
+            .push_str("| Activity | Time | Target | Targets Met | Pomodoros | Longest Session |\n");
+        markdown.push_str("| --- | ---: | ---: | ---: | ---: | ---: |\n");
+        let total = self.total();
+        for activity in self.activities.iter().chain([&total]) {
+            let name = if activity.id.is_none() {
+                format!("**{}**", activity.name)
+            } else {
+                activity.name.replace('|', "\\|")
+            };
+            markdown.push_str(&format!(
+                "| {name} | {} | {} | {}/{} | {} | {} |\n",
+                format_minutes(activity.minutes),
+                format_minutes(activity.target_minutes),
+                activity.days_met,
+                activity.days_with_target,
+                activity.pomodoros,
+                format_minutes(activity.longest_session_minutes)
+            ));
+        }
+        markdown
+    }
+
+    fn html(&self) -> String {
+        let title = format!("Time Report: {} to {}", self.from, self.to);
+        let mut rows = String::new();
+        let total = self.total();
+        for activity in self.activities.iter().chain([&total]) {
+            let cell = if activity.id.is_none() { "th" } else { "td" };
+            rows.push_str(&format!(
+                "      <tr><{cell}>{}</{cell}><td>{}</td><td>{}</td><td>{}/{}</td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&activity.name),
+                format_minutes(activity.minutes),
+                format_minutes(activity.target_minutes),
+                activity.days_met,
+                activity.days_with_target,
+                activity.pomodoros,
+                format_minutes(activity.longest_session_minutes)
+            ));
+        }
+        format!(
+            r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>{title}</title>
+    <style>
+      body {{ font-family: sans-serif; }}
+      table {{ border-collapse: collapse; }}
+      th, td {{ border: 1px solid #ccc; padding: 4px 8px; }}
+      td {{ text-align: right; }}
+      th:first-child, td:first-child {{ text-align: left; }}
+    </style>
+  </head>
+  <body>
+    <h1>{title}</h1>
+    <p>{total} in total over {days} tracked days.</p>
+    <table>
+      <tr><th>Activity</th><th>Time</th><th>Target</th><th>Targets Met</th><th>Pomodoros</th><th>Longest Session</th></tr>
+{rows}    </table>
+  </body>
+</html>
+"#,
+            total = format_minutes(total.minutes),
+            days = self.days
+        )
+    }
+
+    /// Leaves out the total, which spreadsheets can work out, and keeps times in minutes
+    fn csv(&self) -> String {
+        let mut csv = String::from(
+            "activity,minutes,target_minutes,days_met,days_with_target,pomodoros,longest_session_minutes\n",
+        );
+        for activity in &self.activities {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                escape_csv(&activity.name),
+                activity.minutes,
+                activity.target_minutes,
+                activity.days_met,
+                activity.days_with_target,
+                activity.pomodoros,
+                activity.longest_session_minutes
+            ));
+        }
+        csv
+    }
+}
+
+/// Formats minutes like `2h 05min`, or `45min` if there is less than an hour
+fn format_minutes(minutes: usize) -> String {
+    if minutes < 60 {
+        format!("{minutes}min")
+    } else {
+        format!("{}h {:02}min", minutes / 60, minutes % 60)
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quotes a field if it has a character which CSV treats specially
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Writes a report of every archived day from `from` to `to`, and of the stored day if it is within them
+pub fn write_report(
+    state: &State,
+    from: NaiveDate,
+    to: NaiveDate,
+    format: ReportFormat,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    let today = state.day_record();
+    let days = state
+        .history()
+        .chain([&today])
+        .filter(|day| from <= day.date && day.date <= to);
+    let report = Report::new(from, to, days);
+    let contents = match format {
+        ReportFormat::Md => report.markdown(),
+        ReportFormat::Html => report.html(),
+        ReportFormat::Csv => report.csv(),
+    };
+    match output {
+        Some(path) => {
+            std::fs::write(&path, contents)?;
+            eprintln!("Wrote the report to {}", path.display());
+        }
+        None => print!("{contents}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use super::*;
+    use crate::state::{ArchivedActivity, Session};
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2025, 6, day).unwrap()
+    }
+
+    fn session(id: usize, minutes: i64, pomo_minutes: Option<usize>, overridden: bool) -> LogEntry {
+        let start_time: DateTime<Utc> = "2025-06-02T09:00:00Z".parse().unwrap();
+        LogEntry::Session(Session {
+            activity_id: ActivityId::from_raw(id),
+            start_time,
+            end_time: start_time + TimeDelta::minutes(minutes),
+            pomo_minutes,
+            extended_minutes: 0,
+            overridden,
+            split: false,
+            excluded: vec![],
+        })
+    }
+
+    fn day(day: u32, acheived: [usize; 2], log: Vec<LogEntry>) -> DayRecord {
+        DayRecord {
+            date: date(day),
+            activities: vec![
+                ArchivedActivity {
+                    id: ActivityId::from_raw(1),
+                    name: "reading".to_string(),
+                    target_minutes: 60,
+                    acheived_minutes: acheived[0],
+                },
+                ArchivedActivity {
+                    id: ActivityId::from_raw(2),
+                    name: "gym, weights".to_string(),
+                    target_minutes: 0,
+                    acheived_minutes: acheived[1],
+                },
+            ],
+            log,
+        }
+    }
+
+    fn report() -> Report {
+        let days = [
+            day(
+                2,
+                [75, 0],
+                vec![session(1, 25, Some(25), false), session(1, 50, None, false)],
+            ),
+            day(
+                3,
+                [30, 40],
+                vec![
+                    session(1, 10, Some(25), true),
+                    session(2, 40, Some(40), false),
+                ],
+            ),
+        ];
+        Report::new(date(2), date(8), &days)
+    }
+
+    #[test]
+    fn days_are_summed_per_activity() {
+        let report = report();
+        assert_eq!(report.days, 2);
+        let reading = &report.activities[0];
+        assert_eq!((reading.minutes, reading.target_minutes), (105, 120));
+        assert_eq!((reading.days_met, reading.days_with_target), (1, 2));
+        // the second pomodoro was ended early
+        assert_eq!(reading.pomodoros, 1);
+        assert_eq!(reading.longest_session_minutes, 50);
+        let gym = &report.activities[1];
+        assert_eq!((gym.days_met, gym.days_with_target), (0, 0));
+        assert_eq!(report.total().pomodoros, 2);
+    }
+
+    #[test]
+    fn formats_have_a_row_per_activity() {
+        let report = report();
+        let markdown = report.markdown();
+        assert!(markdown.contains("| reading | 1h 45min | 2h 00min | 1/2 | 1 | 50min |"));
+        assert!(markdown.contains("| **Total** | 2h 25min |"));
+        assert!(report.html().contains("<tr><td>gym, weights</td>"));
+        assert_eq!(
+            report.csv().lines().collect::<Vec<_>>(),
+            vec![
+                "activity,minutes,target_minutes,days_met,days_with_target,pomodoros,longest_session_minutes",
+                "reading,105,120,1,2,1,50",
+                "\"gym, weights\",40,0,0,0,1,40",
+            ]
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(escape_html("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+        assert_eq!(escape_csv("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(escape_csv("plain"), "plain");
+    }
+}
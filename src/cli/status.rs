@@ -0,0 +1,144 @@
+//! `timetrack status`, which is meant to be run every few seconds by status bars such as tmux, waybar or polybar
+
+use chrono::Utc;
+use clap::ValueEnum;
+use color_eyre::Result;
+use serde::Serialize;
+
+use crate::state::State;
+use crate::track::recommended_activity;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusFormat {
+    /// A single line of text
+    Plain,
+    /// A JSON object with every field
+    Json,
+    /// The JSON object which waybar's custom modules expect
+    Waybar,
+}
+
+/// Everything `timetrack status` reports about the ongoing session
+#[derive(Debug, Serialize)]
+struct Status {
+    activity: Option<String>,
+    /// How long the ongoing session has been running
+    elapsed_minutes: Option<usize>,
+    /// True if the ongoing session is paused
+    paused: bool,
+    /// The time spent on the ongoing activity today, including the ongoing session
+    today_minutes: Option<usize>,
+    target_minutes: Option<usize>,
+    pomodoro_minutes_left: Option<usize>,
+    /// How long is left of the break between two pomodoros, if one is ongoing
+    break_minutes_left: Option<usize>,
+    /// The activity to work on next, if no session is ongoing
+    recommended: Option<String>,
+}
+impl Status {
+    fn new(state: &State) -> Self {
+        let activity = state.current_activity();
+        Self {
+            activity: activity.map(|x| x.name().to_string()),
+            elapsed_minutes: state
+                .current_session_duration()
+                .map(|duration| duration.num_minutes().max(0) as usize),
+            paused: state.is_paused(),
+            today_minutes: activity.map(|x| {
+                state.acheived_minutes(x.id()) + state.current_task_minutes().unwrap_or(0)
+            }),
+            target_minutes: activity.map(|x| x.today_target_minutes()),
+            pomodoro_minutes_left: state.pomo_minutes().map(|pomo_minutes| {
+                pomo_minutes.saturating_sub(
+                    state
+                        .current_session_duration()
+                        .expect("should have a current session")
+                        .num_minutes()
+                        .max(0) as usize,
+                )
+            }),
+            break_minutes_left: state.pomodoro_break().map(|pomodoro_break| {
+                (pomodoro_break.end_time() - Utc::now())
+                    .num_minutes()
+                    .max(0) as usize
+            }),
+            recommended: recommended_activity(state)
+                .ok()
+                .map(|x| x.name().to_string()),
+        }
+    }
+
+    fn text(&self) -> String {
+        match (&self.activity, self.break_minutes_left, &self.recommended) {
+            (Some(activity), _, _) => {
+                let elapsed = self.elapsed_minutes.unwrap_or(0);
+                let paused = if self.paused { " PAUSED" } else { "" };
+                match self.pomodoro_minutes_left {
+                    Some(left) => format!("{activity} {elapsed}min ({left}min left){paused}"),
+                    None => format!("{activity} {elapsed}min{paused}"),
+                }
+            }
+            (None, Some(left), _) => format!("break ({left}min left)"),
+            (None, None, Some(recommended)) => format!("idle, next: {recommended}"),
+            (None, None, None) => "idle".to_string(),
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        if self.paused {
+            "paused"
+        } else if self.pomodoro_minutes_left.is_some() {
+            "pomodoro"
+        } else if self.activity.is_some() {
+            "ongoing"
+        } else if self.break_minutes_left.is_some() {
+            "break"
+        } else {
+            "idle"
+        }
+    }
+
+    fn tooltip(&self) -> String {
+        match (&self.activity, self.today_minutes, self.target_minutes) {
+            (Some(activity), Some(today), Some(target)) => {
+                format!("{activity}: {today} / {target}min today")
+            }
+            _ => self.text(),
+        }
+    }
+}
+
+/// The fields of a waybar custom module with `"return-type": "json"`
+#[derive(Debug, Serialize)]
+struct WaybarStatus {
+    text: String,
+    alt: &'static str,
+    tooltip: String,
+    class: &'static str,
+    /// Progress towards the ongoing activity's target for today
+    #[serde(skip_serializing_if = "Option::is_none")]
+    percentage: Option<usize>,
+}
+
+pub fn print_status(state: &State, format: StatusFormat) -> Result<()> {
+    let status = Status::new(state);
+    match format {
+        StatusFormat::Plain => println!("{}", status.text()),
+        StatusFormat::Json => println!("{}", serde_json::to_string(&status)?),
+        StatusFormat::Waybar => println!(
+            "{}",
+            serde_json::to_string(&WaybarStatus {
+                text: status.text(),
+                alt: status.class(),
+                tooltip: status.tooltip(),
+                class: status.class(),
+                percentage: status
+                    .today_minutes
+                    .zip(status.target_minutes)
+                    .filter(|(_, target)| *target > 0)
+                    .map(|(today, target)| (today * 100 / target).min(100)),
+            })?
+        ),
+    }
+    Ok(())
+}
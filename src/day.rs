@@ -0,0 +1,41 @@
+//! Days are counted in local time, and start at `TIMETRACK_DAY_START` (midnight by default),
+//! so that late-night sessions can count towards the previous day.
+
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeDelta, TimeZone, Utc};
+use color_eyre::Result;
+use color_eyre::eyre::eyre;
+
+/// Reads the time of day at which a new day starts from `TIMETRACK_DAY_START` (formatted like `04:00`)
+pub fn day_start_from_env() -> Result<NaiveTime> {
+    match std::env::var("TIMETRACK_DAY_START") {
+        Ok(day_start) => NaiveTime::parse_from_str(&day_start, "%H:%M").map_err(|_| {
+            eyre!("TIMETRACK_DAY_START should be a time like 04:00, but it was \"{day_start}\"")
+        }),
+        Err(_) => Ok(NaiveTime::MIN),
+    }
+}
+
+/// The day which `time` counts towards
+pub fn day_of(time: DateTime<Utc>, day_start: NaiveTime) -> NaiveDate {
+    (time.with_timezone(&Local).naive_local() - (day_start - NaiveTime::MIN)).date()
+}
+
+pub fn today(day_start: NaiveTime) -> NaiveDate {
+    day_of(Utc::now(), day_start)
+}
+
+/// The moment at which `date` starts
+pub fn start_of(date: NaiveDate, day_start: NaiveTime) -> DateTime<Utc> {
+    let start = date.and_time(day_start);
+    Local
+        .from_local_datetime(&start)
+        .earliest()
+        // the start of the day fell into a daylight saving gap, so it starts once the clocks have gone forward
+        .or_else(|| {
+            Local
+                .from_local_datetime(&(start + TimeDelta::hours(1)))
+                .earliest()
+        })
+        .expect("the time after a daylight saving gap should exist")
+        .with_timezone(&Utc)
+}
@@ -32,6 +32,14 @@ impl HelpWindow {
                 " when an input widget is not selected, and a pomodoro session is not ongoing."
                     .into(),
             ],
+            vec![
+                "Undo and Redo:".yellow().bold(),
+                " Press ".into(),
+                "<Ctrl+z>".blue().bold(),
+                " to undo the last change to your activities, sessions or todos, and ".into(),
+                "<Ctrl+y>".blue().bold(),
+                " to redo it. Changes can be undone until timetrack exits, a new day starts, or a pomodoro or break ends on its own.".into(),
+            ],
             vec![],
             vec!["Activities".yellow().bold().underlined()],
             vec![
@@ -44,6 +52,20 @@ impl HelpWindow {
                 "Activities".green().bold(),
                 " tab, input the name of the activity, then input the daily target and press Enter.".into(),
             ],
+            vec![
+                "Schedules:".yellow().bold(),
+                " Run ".into(),
+                "timetrack schedule".blue().bold(),
+                " to give an activity a target on some days of the week only, or a weekly target spread over the days left in the week. Activities with no target today are shown as ".into(),
+                "DAY OFF".dark_gray(),
+                ". Changing the target of a scheduled activity only changes today's target.".into(),
+            ],
+            vec![
+                "Carrying Time Over:".yellow().bold(),
+                " Run ".into(),
+                "timetrack carry-over".blue().bold(),
+                " to add the time an activity falls short of its target to the next day's target. The balance is shown after the target, where + is time owed and - is time worked ahead.".into(),
+            ],
             vec![
                 "Start a Pomodoro Session:".yellow().bold(),
                 " Press ".into(),
@@ -54,16 +76,94 @@ impl HelpWindow {
                 "<Enter>".blue().bold(),
                 " to start the session.".into(),
             ],
+            vec![
+                "Choosing the Next Activity:".yellow().bold(),
+                " Pomodoro sessions are for the activity shown as ".into(),
+                "Next".green().bold(),
+                ". Press ".into(),
+                "<m>".blue().bold(),
+                " on the ".into(),
+                "Activities".green().bold(),
+                " section to change how it is picked: lowest time, lowest ratio, largest remaining, round robin, weighted random or priority. Press ".into(),
+                "<+>".blue().bold(),
+                " and ".into(),
+                "<->".blue().bold(),
+                " to change the priority of the selected activity.".into(),
+            ],
+            vec![
+                "Skipping a Recommendation:".yellow().bold(),
+                " The ".into(),
+                "Up Next".green().bold(),
+                " section shows the top few activities and why they are ranked there. Press ".into(),
+                "<n>".blue().bold(),
+                " to start a pomodoro session on the second one instead of the first, which is logged as a skip.".into(),
+            ],
             vec![
                 "Ending a Pomodoro Session:".yellow().bold(),
-                " The session will end automatically when the timer is up. If the ".into(),
+                " When the timer is up, you will be asked whether to extend the session or finish it. If the ".into(),
                 "notifications".green().bold(),
-                " feature is activated, then you will get a notification when the session is over. The session can be stopped early by going to the ".into(),
+                " feature is activated, then you will get a notification when the timer is up. The session can be stopped early by going to the ".into(),
                 "Ongoing".green().bold(),
                 " widget and pressing ".into(),
                 "<Backspace>".blue().bold(),
                 ".".into(),
             ],
+            vec![
+                "Extending a Pomodoro Session:".yellow().bold(),
+                " When the timer is up, press ".into(),
+                "<a>".blue().bold(),
+                ", ".into(),
+                "<s>".blue().bold(),
+                " or ".into(),
+                "<d>".blue().bold(),
+                " in the Track tab to keep going for 5, 10 or 15 more minutes, or ".into(),
+                "<f>".blue().bold(),
+                " to finish the session. It finishes on its own if you don't answer within 5 minutes.".into(),
+            ],
+            vec![
+                "Pausing a Session:".yellow().bold(),
+                " Press ".into(),
+                "<Space>".blue().bold(),
+                " on the ".into(),
+                "Ongoing".green().bold(),
+                " widget to pause the ongoing session, and again to resume it. Paused time doesn't count towards the session.".into(),
+            ],
+            vec![
+                "Pomodoro Breaks:".yellow().bold(),
+                " A break starts when a pomodoro session is over, and the next session starts on the recommended activity when the break is over. Every fourth break is a long one. Press ".into(),
+                "<Backspace>".blue().bold(),
+                " on the ".into(),
+                "Ongoing".green().bold(),
+                " widget during a break to end the cycle.".into(),
+            ],
+            vec![
+                "Stale Sessions:".yellow().bold(),
+                " If timetrack was open during a session but stopped running for a while, for example because the computer was asleep, or a session runs for hours, you will be asked what to do with that time. Press ".into(),
+                "<k>".blue().bold(),
+                " to keep it, ".into(),
+                "<t>".blue().bold(),
+                " to end the session where that time starts, or ".into(),
+                "<d>".blue().bold(),
+                " to discard it and keep the session going.".into(),
+            ],
+            vec![
+                "Streaks:".yellow().bold(),
+                " The ".into(),
+                "Streaks".green().bold(),
+                " tab shows how many days in a row each activity's target was met, and a heatmap of the selected activity, which is greener the more of the target was met. Days off don't break a streak.".into(),
+            ],
+            vec![
+                "Stats:".yellow().bold(),
+                " The ".into(),
+                "Stats".green().bold(),
+                " tab charts the time tracked over the last few days. Press ".into(),
+                "<r>".blue().bold(),
+                " to change the number of days, and ".into(),
+                "<Left>".blue().bold(),
+                " and ".into(),
+                "<Right>".blue().bold(),
+                " to show a single activity.".into(),
+            ],
             vec![],
             vec!["Todo List".yellow().bold().underlined()],
             vec![
@@ -155,6 +255,18 @@ impl Window for HelpWindow {
             }) => {
                 return WindowActionResult::ThirdWindow;
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('4'),
+                ..
+            }) => {
+                return WindowActionResult::FourthWindow;
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('5'),
+                ..
+            }) => {
+                return WindowActionResult::FifthWindow;
+            }
             Event::Key(KeyEvent {
                 code: KeyCode::Char('q'),
                 ..
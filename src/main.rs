@@ -1,31 +1,57 @@
+mod cli;
+mod day;
 mod help;
 mod input_widget;
+mod migrations;
+mod notifier;
+mod pomodoro;
+mod recommend;
+mod schedule;
+mod stale;
 mod state;
+mod stats;
+mod storage;
+mod streaks;
 mod todo;
 mod track;
 
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Duration;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
 
-use chrono::Utc;
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::Parser;
+use cli::Cli;
 use color_eyre::Result;
 use help::HelpWindow;
-use ratatui::crossterm::event;
+use notifier::{Notification, NotificationAction, Notifier};
+use pomodoro::{PomodoroBreak, PomodoroSettings, TimeUpAction};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use ratatui::layout::{Constraint, Layout};
 use ratatui::style::{Color, Stylize};
 use ratatui::text::Line;
 use ratatui::widgets::{Block, Paragraph, Widget};
 use ratatui::{DefaultTerminal, Frame};
-use state::{State, StateBuilder};
+use stale::StalePrompt;
+use state::State;
+use stats::StatsWindow;
+use storage::{StateLock, StorageKind};
+use streaks::StreaksWindow;
 use todo::TodoWindow;
 use track::TrackWindow;
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        return cli::run(command);
+    }
     let mut terminal = ratatui::init();
-    let result = App::new()?.run(&mut terminal);
+    let result = App::new().and_then(|mut app| app.run(&mut terminal));
     ratatui::restore();
     result
 }
@@ -33,6 +59,11 @@ fn main() -> Result<()> {
 trait Window: std::fmt::Debug {
     fn draw(&self, state: &State, frame: &mut Frame, area: ratatui::layout::Rect);
     fn handle_event(&mut self, state: &mut State, event: &event::Event) -> WindowActionResult;
+
+    /// True while a text input has focus, in which case keys are typed into it rather than used as shortcuts
+    fn is_typing(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -40,17 +71,43 @@ enum AppWindow {
     Track,
     Todo,
     Help,
+    Streaks,
+    Stats,
 }
 
+/// How long to wait for user input before redrawing and checking timers
+const TICK: Duration = Duration::from_secs(1);
+/// The minimum time between two saves of the state file
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(2);
+/// How long after a break ends the next work block may still start on its own
+const BREAK_GRACE: TimeDelta = TimeDelta::minutes(1);
+/// How long a pomodoro whose timer is up waits to be extended before it finishes on its own
+const TIME_UP_TIMEOUT: TimeDelta = TimeDelta::minutes(5);
+
 struct App {
     state: State,
     exit: bool,
     windows: HashMap<AppWindow, Box<dyn Window>>,
     current_window: AppWindow,
+    /// Shown over the current window when the ongoing session has stale time
+    stale_prompt: Option<StalePrompt>,
+    /// Shown in the header until the next key press
+    message: Option<String>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    /// Receives the IDs of actions picked on notifications
+    notification_actions: Receiver<String>,
+    /// True once the user has been told that the ongoing pomodoro's time is up
+    time_up_notified: bool,
+    last_save: Instant,
+    /// Set when the process receives SIGINT, SIGTERM or SIGHUP
+    termination_requested: Arc<AtomicBool>,
+    /// `None` if another instance holds the lock, in which case `state` is read-only.
+    /// This is declared after `state` so that the lock is held until `state` has been saved.
+    _state_lock: Option<StateLock>,
 }
 impl App {
     fn new() -> Result<Self> {
-        let state = load_state()?;
+        let (state, state_lock) = load_state(false)?;
         let mut windows = HashMap::new();
         windows.insert(
             AppWindow::Track,
@@ -64,19 +121,81 @@ impl App {
             AppWindow::Help,
             Box::new(HelpWindow::new()) as Box<dyn Window>,
         );
+        windows.insert(
+            AppWindow::Streaks,
+            Box::new(StreaksWindow::new()) as Box<dyn Window>,
+        );
+        windows.insert(
+            AppWindow::Stats,
+            Box::new(StatsWindow::new()) as Box<dyn Window>,
+        );
+        let termination_requested = Arc::new(AtomicBool::new(false));
+        {
+            let termination_requested = termination_requested.clone();
+            ctrlc::set_handler(move || termination_requested.store(true, Ordering::SeqCst))?;
+        }
+        let (action_sender, notification_actions) = mpsc::channel();
         Ok(Self {
             state,
             exit: false,
             current_window: AppWindow::Track,
             windows,
+            stale_prompt: None,
+            message: None,
+            notifiers: notifier::from_env(action_sender)?,
+            notification_actions,
+            time_up_notified: false,
+            last_save: Instant::now(),
+            termination_requested,
+            _state_lock: state_lock,
         })
     }
 
     fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
         while !self.exit {
+            self.tick();
             terminal.draw(|frame| self.draw(frame))?;
             self.handle_events()?;
+            if self.termination_requested.load(Ordering::SeqCst) {
+                self.exit = true;
+            }
+            if self.state.is_dirty() && self.last_save.elapsed() >= SAVE_DEBOUNCE {
+                self.save()?;
+            }
+        }
+        // stale time which hasn't been decided on yet is asked about again next time
+        if self.stale_prompt.is_none() {
+            self.state.forget_seen();
+        }
+        self.save()
+    }
+
+    /// Keeps the state up to date with the passing of time
+    fn tick(&mut self) {
+        let now = Utc::now();
+        if self.stale_prompt.is_none() && !self.state.is_read_only() {
+            self.stale_prompt = self.state.stale_time(now).map(StalePrompt::new);
+        }
+        // the session can't move on until the user has decided what to do about its stale time
+        if self.stale_prompt.is_some() {
+            return;
+        }
+        if self.state.date() != self.state.today() {
+            self.state.refresh();
+        }
+        self.state.mark_seen(now);
+        while let Ok(id) = self.notification_actions.try_recv() {
+            if let Some(action) = TimeUpAction::from_id(&id) {
+                self.handle_time_up_action(action);
+            }
         }
+        self.end_finished_pomodoro(now);
+        self.end_finished_break(now);
+    }
+
+    fn save(&mut self) -> Result<()> {
+        self.state.save()?;
+        self.last_save = Instant::now();
         Ok(())
     }
 
@@ -86,24 +205,76 @@ impl App {
 
         frame.render_widget(
             &HeaderWidget {
-                tabs: vec!["Track Activities", "Todo List", "Help"],
+                tabs: vec!["Track Activities", "Todo List", "Help", "Streaks", "Stats"],
                 selected: match self.current_window {
                     AppWindow::Track => 0,
                     AppWindow::Todo => 1,
                     AppWindow::Help => 2,
+                    AppWindow::Streaks => 3,
+                    AppWindow::Stats => 4,
                 },
+                read_only: self.state.is_read_only(),
+                message: self.message.as_deref(),
             },
             header_area,
         );
 
         self.windows[&self.current_window].draw(&self.state, frame, main_area);
+        if let Some(stale_prompt) = &self.stale_prompt {
+            stale_prompt.draw(&self.state, frame, main_area);
+        }
     }
 
     fn handle_events(&mut self) -> std::io::Result<()> {
-        // NOTE: 10 seconds might be a long time!
-        if event::poll(Duration::from_secs(10))? {
+        if event::poll(TICK)? {
             // NOTE: this is NOT blocking!
             let evt = event::read()?;
+            if let Some(stale_prompt) = &self.stale_prompt {
+                if stale_prompt.handle_event(&mut self.state, &evt) {
+                    self.stale_prompt = None;
+                }
+                return Ok(());
+            }
+            if let Event::Key(_) = evt {
+                self.message = None;
+            }
+            match evt {
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('z'),
+                    modifiers: KeyModifiers::CONTROL,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    self.message = Some(match self.state.undo() {
+                        Some(label) => format!("Undid {label}"),
+                        None => "Nothing to undo".to_string(),
+                    });
+                    return Ok(());
+                }
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('y'),
+                    modifiers: KeyModifiers::CONTROL,
+                    kind: KeyEventKind::Press,
+                    ..
+                }) => {
+                    self.message = Some(match self.state.redo() {
+                        Some(label) => format!("Redid {label}"),
+                        None => "Nothing to redo".to_string(),
+                    });
+                    return Ok(());
+                }
+                _ => (),
+            }
+            if self.state.pomodoro_time_up().is_some()
+                && let Some(action) = time_up_action(
+                    &evt,
+                    self.current_window,
+                    self.windows[&self.current_window].as_ref(),
+                )
+            {
+                self.handle_time_up_action(action);
+                return Ok(());
+            }
             let result = self
                 .windows
                 .get_mut(&self.current_window)
@@ -119,44 +290,158 @@ impl App {
                 WindowActionResult::FirstWindow => self.current_window = AppWindow::Track,
                 WindowActionResult::SecondWindow => self.current_window = AppWindow::Todo,
                 WindowActionResult::ThirdWindow => self.current_window = AppWindow::Help,
+                WindowActionResult::FourthWindow => self.current_window = AppWindow::Streaks,
+                WindowActionResult::FifthWindow => self.current_window = AppWindow::Stats,
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a notification everywhere it should go, and shows any failures in the header
+    fn notify(&mut self, notification: &Notification) {
+        for notifier in &self.notifiers {
+            if let Err(error) = notifier.notify(notification) {
+                self.message = Some(format!("Couldn't send a notification: {error}"));
             }
         }
-        if let Some(pomo_minutes) = self.state.pomo_minutes() {
-            if self
+    }
+
+    /// Asks whether to extend the ongoing pomodoro once its timer is up,
+    /// and finishes it if nobody answers for `TIME_UP_TIMEOUT`
+    fn end_finished_pomodoro(&mut self, now: DateTime<Utc>) {
+        let Some(time_up) = self.state.pomodoro_time_up() else {
+            self.time_up_notified = false;
+            return;
+        };
+        if now - time_up >= TIME_UP_TIMEOUT {
+            self.finish_pomodoro(true);
+        } else if !self.time_up_notified {
+            self.time_up_notified = true;
+            let activity_name = self
                 .state
-                .current_session_duration()
-                .expect("should have a current session")
-                .num_minutes()
-                >= pomo_minutes as i64
-            {
-                let activity_id = self
-                    .state
-                    .current_id()
-                    .expect("should be able to get current ID");
-                self.state
-                    .end_activity(true)
-                    .expect("should be able to end activity in pomo!");
-                let activity_name = self
-                    .state
-                    .activity_by_id(activity_id)
-                    .expect("should be able to get activity")
-                    .name();
-
-                #[cfg(feature = "mac-notifications")]
-                mac_notification_sys::send_notification(
-                    "Pomodoro Session Over!!",
-                    Some(activity_name),
-                    &format!("You've worked for {pomo_minutes}min on {activity_name}!"),
-                    Some(
-                        mac_notification_sys::Notification::new()
-                            .asynchronous(true)
-                            .wait_for_click(true),
-                    ),
-                )
-                .expect("should be able to send notification!");
+                .current_activity()
+                .expect("should be able to get activity")
+                .name()
+                .to_string();
+            self.notify(&Notification {
+                title: "Pomodoro Time's Up!!".to_string(),
+                body: format!(
+                    "Keep going on {activity_name} for a few more minutes, or finish the session."
+                ),
+                subtitle: activity_name,
+                actions: TimeUpAction::ALL
+                    .into_iter()
+                    .map(|action| NotificationAction {
+                        id: action.id(),
+                        label: action.label(),
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    /// Applies the user's decision about a pomodoro whose timer is up
+    fn handle_time_up_action(&mut self, action: TimeUpAction) {
+        if self.state.pomodoro_time_up().is_none() {
+            return;
+        }
+        match action {
+            TimeUpAction::Extend(minutes) => {
+                let _ = self.state.extend_pomodoro(minutes);
             }
+            TimeUpAction::Finish => self.finish_pomodoro(false),
         }
-        Ok(())
+    }
+
+    /// Ends the ongoing work block, where `automatic` is true if nobody asked for it,
+    /// in which case it can't be undone
+    fn finish_pomodoro(&mut self, automatic: bool) {
+        let Some(pomo_minutes) = self.state.pomo_minutes() else {
+            return;
+        };
+        let activity_name = self
+            .state
+            .current_activity()
+            .expect("should be able to get activity")
+            .name()
+            .to_string();
+        let pomodoro_break = if automatic {
+            self.state.automatic(State::finish_work_block)
+        } else {
+            self.state.finish_work_block()
+        };
+        let body = match pomodoro_break {
+            Some(pomodoro_break) => format!(
+                "You've worked for {pomo_minutes}min on {activity_name}! Take a {}min {} break.",
+                pomodoro_break.minutes,
+                if pomodoro_break.long { "long" } else { "short" }
+            ),
+            None => format!("You've worked for {pomo_minutes}min on {activity_name}!"),
+        };
+        self.notify(&Notification {
+            title: "Pomodoro Session Over!!".to_string(),
+            body,
+            subtitle: activity_name,
+            actions: vec![],
+        });
+    }
+
+    /// Starts the next work block of the cycle once its break is over
+    fn end_finished_break(&mut self, now: DateTime<Utc>) {
+        let Some(end_time) = self.state.pomodoro_break().map(PomodoroBreak::end_time) else {
+            return;
+        };
+        if now < end_time {
+            return;
+        }
+        // if the break ended a while ago, nobody is there to start working
+        let missed = now - end_time > BREAK_GRACE;
+        let next = (!missed)
+            .then(|| track::recommended_activity(&self.state).ok())
+            .flatten()
+            .map(|activity| (activity.id(), activity.name().to_string()));
+        self.state
+            .automatic(|state| state.end_break(next.as_ref().map(|(id, _)| *id)));
+        let work_minutes = self.state.pomo_minutes();
+        let (body, subtitle) = match (next, work_minutes) {
+            (Some((_, activity_name)), Some(work_minutes)) => (
+                format!("Time to work on {activity_name} for {work_minutes}min!"),
+                activity_name,
+            ),
+            _ if missed => (
+                "The break ended a while ago, so the pomodoro cycle is over.".to_string(),
+                String::new(),
+            ),
+            _ => (
+                "There's nothing left to work on, so the pomodoro cycle is over.".to_string(),
+                String::new(),
+            ),
+        };
+        self.notify(&Notification {
+            title: "Break Over!!".to_string(),
+            subtitle,
+            body,
+            actions: vec![],
+        });
+    }
+}
+
+/// The action picked by `event` for a pomodoro whose timer is up.
+/// These keys only work in the Track window, where they are shown, and not while it is taking text input.
+fn time_up_action(
+    event: &Event,
+    current_window: AppWindow,
+    window: &dyn Window,
+) -> Option<TimeUpAction> {
+    match event {
+        Event::Key(KeyEvent {
+            code: KeyCode::Char(keybind),
+            kind: KeyEventKind::Press,
+            ..
+        }) if current_window == AppWindow::Track && !window.is_typing() => {
+            TimeUpAction::from_keybind(*keybind)
+        }
+        _ => None,
     }
 }
 
@@ -167,40 +452,63 @@ enum WindowActionResult {
     FirstWindow,
     SecondWindow,
     ThirdWindow,
+    FourthWindow,
+    FifthWindow,
 }
 
-fn load_state() -> Result<State> {
-    let path = stored_state_file_path()?;
-    let stored_state: StateBuilder = if path.exists() {
-        serde_json::from_str(&std::fs::read_to_string(path)?)?
+/// Loads the stored state, and locks it unless `read_only` is true or another instance holds the lock.
+/// Nothing is written when `read_only` is true.
+fn load_state(read_only: bool) -> Result<(State, Option<StateLock>)> {
+    let kind = StorageKind::from_env()?;
+    let path = stored_state_file_path(kind)?;
+    if !read_only && let Some(dirs) = path.parent() {
+        std::fs::create_dir_all(dirs)?;
+    }
+    let storage = storage::open(kind, path, backup_count())?;
+    let state_lock = if read_only {
+        None
     } else {
-        if let Some(dirs) = path.parent() {
-            std::fs::create_dir_all(dirs)?;
-        }
-        std::fs::write(path, "{}")?;
-        serde_json::from_str("{}")?
+        storage::try_lock(storage.path())?
     };
-    let state: State = stored_state.into();
-    if state.date() == Utc::now().date_naive() {
-        Ok(state)
-    } else {
-        Ok(state.refresh())
+    let builder = storage.load(state_lock.is_none())?;
+    let has_date = builder.date.is_some();
+    let mut state: State = builder.into();
+    state.set_storage(storage);
+    state.set_day_start(day::day_start_from_env()?);
+    if !has_date {
+        // a new state starts on the day it is first opened, which depends on when days start
+        state.set_date(state.today());
     }
+    state.set_pomodoro_settings(PomodoroSettings::from_env()?);
+    if state_lock.is_none() {
+        state.set_read_only();
+    }
+    Ok((state, state_lock))
 }
 
-fn stored_state_file_path() -> Result<PathBuf, color_eyre::eyre::Error> {
+fn stored_state_file_path(kind: StorageKind) -> Result<PathBuf, color_eyre::eyre::Error> {
     Ok(PathBuf::from_str(
         if let Ok(file_path) = std::env::var("TIMETRACK_STATE_FILE_PATH") {
             file_path
         } else {
             let home = std::env::var("HOME")?;
-            format!("{home}/.timetrack/state.json")
+            format!(
+                "{home}/.timetrack/{file_name}",
+                file_name = kind.default_file_name()
+            )
         }
         .as_str(),
     )
     .unwrap())
 }
 
+fn backup_count() -> usize {
+    std::env::var("TIMETRACK_BACKUP_COUNT")
+        .ok()
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(storage::DEFAULT_BACKUP_COUNT)
+}
+
 fn instruction_line(values: Vec<(&str, &str)>) -> Line<'static> {
     Line::from(
         values
@@ -220,6 +528,8 @@ fn instruction_line(values: Vec<(&str, &str)>) -> Line<'static> {
 struct HeaderWidget<'a> {
     tabs: Vec<&'a str>,
     selected: usize,
+    read_only: bool,
+    message: Option<&'a str>,
 }
 impl<'a> Widget for &HeaderWidget<'a> {
     fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
@@ -233,6 +543,12 @@ impl<'a> Widget for &HeaderWidget<'a> {
                 .map(|(i, x)| (i + 1, x))
                 .map(|(i, x)| (i == self.selected + 1, format!("{x} [{i}] ")))
                 .map(|(selected, text)| if selected { text.yellow() } else { text.into() })
+                .chain(self.read_only.then(|| {
+                    " READ-ONLY: another instance of timetrack is using this state file, so changes will not be saved "
+                        .black()
+                        .on_red()
+                }))
+                .chain(self.message.map(|message| format!(" {message} ").yellow().italic()))
                 .collect::<Vec<_>>(),
         )])
         .block(Block::bordered().style(Color::DarkGray))
@@ -240,3 +556,34 @@ impl<'a> Widget for &HeaderWidget<'a> {
         .render(area, buf);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn time_up_keys_only_work_in_the_track_window() {
+        let mut state = State::empty();
+        let mut track = TrackWindow::new();
+        assert_eq!(
+            time_up_action(&key(KeyCode::Char('f')), AppWindow::Track, &track),
+            Some(TimeUpAction::Finish)
+        );
+        // Tab moves the focus to the Add Activity input
+        track.handle_event(&mut state, &key(KeyCode::Tab));
+        assert_eq!(
+            time_up_action(&key(KeyCode::Char('f')), AppWindow::Track, &track),
+            None
+        );
+
+        let todo = TodoWindow::new();
+        assert_eq!(
+            time_up_action(&key(KeyCode::Char('a')), AppWindow::Todo, &todo),
+            None
+        );
+    }
+}
@@ -0,0 +1,427 @@
+//! Upgrades state files written by older versions of timetrack.
+//!
+//! Every state file records the `schema_version` it was written with.
+//! Files from before versioning was introduced have no `schema_version`, and are treated as version 1.
+//! `MIGRATIONS[i]` upgrades a file from version `i + 1` to version `i + 2`,
+//! and the steps are applied in order until the file reaches `CURRENT_SCHEMA_VERSION`.
+//!
+//! New fields which older files can leave out, because their defaults mean what older versions did,
+//! don't need a step of their own. They are listed under the current version instead.
+
+use std::fmt::Display;
+
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::state::{
+    Activity, ActivityId, Bucket, Correction, DEFAULT_BUCKET_NAME, DayRecord, LogEntry, TodoItem,
+};
+
+pub const CURRENT_SCHEMA_VERSION: u32 = 5;
+
+type Migration = fn(&mut Map<String, Value>) -> serde_json::Result<()>;
+
+const MIGRATIONS: [Migration; (CURRENT_SCHEMA_VERSION - 1) as usize] = [
+    merge_todos_into_buckets,
+    move_acheived_minutes_into_log,
+    mark_sessions_unsplit,
+    add_optional_fields,
+];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The file was written by a newer version of timetrack
+    NewerSchema {
+        found: u32,
+    },
+    NotAnObject,
+    InvalidSchemaVersion,
+    Json(serde_json::Error),
+}
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::NewerSchema { found } => write!(
+                f,
+                "the state file uses schema version {found}, but this version of timetrack only understands up to version {CURRENT_SCHEMA_VERSION}. Please update timetrack"
+            ),
+            MigrationError::NotAnObject => write!(f, "the state file is not a JSON object"),
+            MigrationError::InvalidSchemaVersion => {
+                write!(
+                    f,
+                    "the state file's schema_version is not a positive integer"
+                )
+            }
+            MigrationError::Json(error) => write!(f, "failed to migrate the state file: {error}"),
+        }
+    }
+}
+impl std::error::Error for MigrationError {}
+
+/// Upgrades a parsed state file to `CURRENT_SCHEMA_VERSION`
+pub fn migrate(value: Value) -> Result<Value, MigrationError> {
+    let Value::Object(mut state) = value else {
+        return Err(MigrationError::NotAnObject);
+    };
+    let version = match state.get("schema_version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version >= 1)
+            .ok_or(MigrationError::InvalidSchemaVersion)?,
+    };
+    if version > CURRENT_SCHEMA_VERSION {
+        return Err(MigrationError::NewerSchema { found: version });
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        migration(&mut state).map_err(MigrationError::Json)?;
+        state.insert("schema_version".to_string(), Value::from(i as u32 + 2));
+    }
+    Ok(Value::Object(state))
+}
+
+fn take<T: for<'de> Deserialize<'de>>(
+    state: &mut Map<String, Value>,
+    key: &str,
+) -> serde_json::Result<Option<T>> {
+    state
+        .remove(key)
+        .filter(|value| !value.is_null())
+        .map(serde_json::from_value)
+        .transpose()
+}
+
+fn put<T: Serialize>(
+    state: &mut Map<String, Value>,
+    key: &str,
+    value: T,
+) -> serde_json::Result<()> {
+    state.insert(key.to_string(), serde_json::to_value(value)?);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TodoItemOld {
+    item: String,
+    bucket: Option<String>,
+}
+
+/// Version 1 stored todos in several places, depending on the version of timetrack which wrote them:
+/// `todo` (a flat list, before buckets existed), `todo_v2` (todos tagged with a bucket name),
+/// `buckets` (bucket names) and `buckets_v2` (buckets containing their todos).
+/// Version 2 only has `buckets`, which contains every bucket and its todos.
+fn merge_todos_into_buckets(state: &mut Map<String, Value>) -> serde_json::Result<()> {
+    let todo: Option<Vec<String>> = take(state, "todo")?;
+    let todo_v2: Option<Vec<TodoItemOld>> = take(state, "todo_v2")?;
+    let bucket_names: Option<Vec<String>> = take(state, "buckets")?;
+    let mut buckets: Vec<Bucket> = take(state, "buckets_v2")?.unwrap_or_default();
+
+    for name in bucket_names.unwrap_or_default() {
+        if buckets.iter().map(|x| x.name()).all(|x| x != name.as_str()) {
+            buckets.push(Bucket::new(name, vec![]));
+        }
+    }
+    let default_bucket = if let Some(default_bucket) =
+        buckets.iter_mut().find(|x| x.name() == DEFAULT_BUCKET_NAME)
+    {
+        default_bucket
+    } else {
+        buckets.push(Bucket::new(String::from(DEFAULT_BUCKET_NAME), vec![]));
+        let last_index = buckets.len() - 1;
+        &mut buckets[last_index]
+    };
+
+    for old_todo in todo.unwrap_or_default() {
+        default_bucket.push_todo(TodoItem::new(old_todo));
+    }
+
+    for todo in todo_v2.unwrap_or_default() {
+        if let Some(target_bucket) = buckets
+            .iter_mut()
+            .find(|x| x.name() == todo.bucket.as_deref().unwrap_or(DEFAULT_BUCKET_NAME))
+        {
+            target_bucket.push_todo(TodoItem::new(todo.item));
+        }
+    }
+
+    put(state, "buckets", buckets)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ActivityOld {
+    target_minutes: usize,
+    acheived_minutes: usize,
+    name: String,
+    id: ActivityId,
+}
+
+/// Version 2 stored activities with their acheived time in `activities`,
+/// or without it in `activities_v2` alongside a `log` of sessions.
+/// Version 3 only has `activities` and `log`, and the old acheived time becomes a correction in the log.
+fn move_acheived_minutes_into_log(state: &mut Map<String, Value>) -> serde_json::Result<()> {
+    let old_activities: Option<Vec<ActivityOld>> = take(state, "activities")?;
+    let mut activities: Vec<Activity> = take(state, "activities_v2")?.unwrap_or_default();
+    let mut log: Vec<LogEntry> = take(state, "log")?.unwrap_or_default();
+    let date = state
+        .get("date")
+        .cloned()
+        .and_then(|date| serde_json::from_value::<NaiveDate>(date).ok())
+        .unwrap_or_else(|| Utc::now().date_naive());
+
+    for old_activity in old_activities.unwrap_or_default() {
+        if old_activity.acheived_minutes > 0 {
+            log.push(LogEntry::Correction(Correction {
+                activity_id: old_activity.id,
+                time: date
+                    .and_hms_opt(0, 0, 0)
+                    .expect("midnight should be a valid time")
+                    .and_utc(),
+                minutes: old_activity.acheived_minutes as i64,
+            }));
+        }
+        activities.push(Activity::new(
+            old_activity.id,
+            old_activity.name,
+            old_activity.target_minutes,
+        ));
+    }
+
+    put(state, "activities", activities)?;
+    put(state, "log", log)
+}
+
+/// Version 4 splits sessions which run past the end of the day, and marks the logged parts with `split`.
+/// Every session logged before then ran within a single day.
+fn mark_sessions_unsplit(state: &mut Map<String, Value>) -> serde_json::Result<()> {
+    let mut log: Vec<LogEntry> = take(state, "log")?.unwrap_or_default();
+    let mut history: Vec<DayRecord> = take(state, "history")?.unwrap_or_default();
+    for entry in log
+        .iter_mut()
+        .chain(history.iter_mut().flat_map(|day| day.log.iter_mut()))
+    {
+        if let LogEntry::Session(session) = entry {
+            session.split = false;
+        }
+    }
+    put(state, "log", log)?;
+    put(state, "history", history)
+}
+
+/// Version 5 adds fields and log entries which older files never have, and which need no change to them:
+/// - time excluded from sessions, by stale time decisions or pauses, and `StaleTime` log entries
+/// - the ongoing pomodoro cycle, `Break` log entries, and the minutes pomodoros were extended by
+/// - the recommendation strategy, the priorities of activities, and `Skip` log entries
+/// - the schedules of activities, their targets for the stored day, and the time they carry over
+///
+/// The version still changes because older versions of timetrack would drop the new fields,
+/// and fail to read the new log entries.
+fn add_optional_fields(_state: &mut Map<String, Value>) -> serde_json::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::recommend::StrategyKind;
+    use crate::schedule::Schedule;
+    use crate::state::{State, StateBuilder};
+
+    fn fixture(contents: &str) -> Value {
+        serde_json::from_str(contents).expect("fixture should be valid JSON")
+    }
+
+    fn load(value: Value) -> State {
+        let builder: StateBuilder =
+            serde_json::from_value(migrate(value).expect("fixture should migrate"))
+                .expect("migrated fixture should match the current schema");
+        State::from(builder)
+    }
+
+    fn bucket_contents(state: &State) -> Vec<(String, Vec<String>)> {
+        state
+            .get_buckets()
+            .map(|bucket| {
+                (
+                    bucket.name().to_string(),
+                    bucket.todos().map(|x| x.item().to_string()).collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unversioned_files_are_version_1() {
+        let migrated = migrate(json!({})).unwrap();
+        assert_eq!(migrated["schema_version"], json!(CURRENT_SCHEMA_VERSION));
+        assert_eq!(migrated["buckets"][0]["name"], json!(DEFAULT_BUCKET_NAME));
+    }
+
+    #[test]
+    fn v1_to_v2_merges_legacy_todos() {
+        let mut state = fixture(include_str!("../fixtures/state_v1_todos.json"))
+            .as_object()
+            .unwrap()
+            .clone();
+        merge_todos_into_buckets(&mut state).unwrap();
+        for key in ["todo", "todo_v2", "buckets_v2"] {
+            assert!(!state.contains_key(key), "{key} should have been removed");
+        }
+        assert_eq!(
+            Value::Object(state),
+            json!({
+                "date": "2025-06-02",
+                "buckets": [
+                    {"name": "work", "todos": ["write report"]},
+                    {"name": "N/A", "todos": ["buy milk", "call bob", "water plants"]},
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn v1_sample_state_keeps_its_buckets() {
+        let state = load(fixture(include_str!("../fixtures/state_v1_buckets.json")));
+        assert_eq!(
+            bucket_contents(&state),
+            vec![
+                ("N/A".to_string(), vec!["hi there!".to_string()]),
+                ("potato".to_string(), vec![]),
+                ("cake".to_string(), vec!["apple".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn v2_to_v3_turns_acheived_minutes_into_corrections() {
+        let mut state = fixture(include_str!("../fixtures/state_v2.json"))
+            .as_object()
+            .unwrap()
+            .clone();
+        move_acheived_minutes_into_log(&mut state).unwrap();
+        assert!(!state.contains_key("activities_v2"));
+        assert_eq!(
+            state["log"],
+            json!([{"Correction": {
+                "activity_id": 1,
+                "time": "2025-06-02T00:00:00Z",
+                "minutes": 25,
+            }}])
+        );
+        assert_eq!(
+            state["activities"],
+            json!([
+                {"target_minutes": 60, "name": "reading", "id": 1},
+                {"target_minutes": 30, "name": "exercise", "id": 2},
+            ])
+        );
+    }
+
+    #[test]
+    fn v1_activities_keep_their_acheived_time() {
+        let state = load(fixture(include_str!(
+            "../fixtures/state_v1_activities.json"
+        )));
+        let acheived: Vec<_> = state
+            .activities()
+            .map(|x| (x.name().to_string(), state.acheived_minutes(x.id())))
+            .collect();
+        assert_eq!(
+            acheived,
+            vec![("reading".to_string(), 25), ("exercise".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn v1_files_with_a_session_log_keep_it() {
+        let state = load(fixture(include_str!("../fixtures/state_v1_log.json")));
+        assert_eq!(state.activities().count(), 1);
+        let id = state.activities().next().unwrap().id();
+        assert_eq!(state.acheived_minutes(id), 40);
+        assert_eq!(state.history().count(), 1);
+    }
+
+    #[test]
+    fn v3_to_v4_marks_sessions_unsplit() {
+        let mut state = fixture(include_str!("../fixtures/state_v3.json"))
+            .as_object()
+            .unwrap()
+            .clone();
+        mark_sessions_unsplit(&mut state).unwrap();
+        assert_eq!(state["log"][0]["Session"]["split"], json!(false));
+        assert_eq!(state["log"][0]["Session"]["pomo_minutes"], json!(25));
+        assert_eq!(state["log"][1]["Correction"].get("split"), None);
+        assert_eq!(
+            state["history"][0]["log"][0]["Session"]["split"],
+            json!(false)
+        );
+
+        let state = load(Value::Object(state));
+        let id = state.activities().next().unwrap().id();
+        assert_eq!(state.acheived_minutes(id), 35);
+    }
+
+    #[test]
+    fn v4_files_get_the_defaults() {
+        let state = load(fixture(include_str!("../fixtures/state_v4.json")));
+        assert_eq!(state.pomodoro_cycle(), None);
+        assert!(!state.is_paused());
+        assert_eq!(state.recommendation_strategy(), StrategyKind::LowestTime);
+        let activity = state.activities().next().unwrap();
+        assert_eq!(activity.schedule(), Schedule::Daily);
+        assert_eq!(activity.carry_over(), None);
+        assert_eq!(activity.today_target_minutes(), 60);
+    }
+
+    #[test]
+    fn current_files_are_saved_as_they_were_read() {
+        let current = fixture(include_str!("../fixtures/state_v5.json"));
+        let builder: StateBuilder = serde_json::from_value(current.clone()).unwrap();
+        assert_eq!(serde_json::to_value(builder).unwrap(), current);
+    }
+
+    #[test]
+    fn current_files_are_unchanged() {
+        let current = fixture(include_str!("../fixtures/state_v5.json"));
+        assert_eq!(migrate(current.clone()).unwrap(), current);
+    }
+
+    #[test]
+    fn migrations_only_run_once() {
+        let once = migrate(fixture(include_str!(
+            "../fixtures/state_v1_activities.json"
+        )))
+        .unwrap();
+        let twice = migrate(once.clone()).unwrap();
+        assert_eq!(once, twice);
+    }
+
+    #[test]
+    fn newer_files_are_rejected() {
+        let mut newer = fixture(include_str!("../fixtures/state_v5.json"));
+        newer["schema_version"] = json!(CURRENT_SCHEMA_VERSION + 1);
+        assert!(matches!(
+            migrate(newer),
+            Err(MigrationError::NewerSchema { found }) if found == CURRENT_SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn invalid_versions_are_rejected() {
+        assert!(matches!(
+            migrate(json!({"schema_version": 0})),
+            Err(MigrationError::InvalidSchemaVersion)
+        ));
+        assert!(matches!(
+            migrate(json!({"schema_version": "3"})),
+            Err(MigrationError::InvalidSchemaVersion)
+        ));
+        assert!(matches!(
+            migrate(json!([])),
+            Err(MigrationError::NotAnObject)
+        ));
+    }
+}
@@ -0,0 +1,194 @@
+//! Notifications, which are sent when a pomodoro session is over.
+//! Where they are sent is chosen at runtime with `TIMETRACK_NOTIFIER`.
+
+#[cfg(all(feature = "linux-notifications", target_os = "linux"))]
+mod dbus;
+#[cfg(all(feature = "mac-notifications", target_os = "macos"))]
+mod mac;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+
+use chrono::Local;
+use color_eyre::Result;
+use color_eyre::eyre::{bail, eyre};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    pub title: String,
+    pub subtitle: String,
+    pub body: String,
+    /// Buttons to show on the notification, where the notifier supports them
+    pub actions: Vec<NotificationAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationAction {
+    /// What is sent back when the action is picked
+    pub id: String,
+    pub label: String,
+}
+
+/// Somewhere notifications can be sent
+pub trait Notifier: std::fmt::Debug {
+    fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Reads the notifiers to use from `TIMETRACK_NOTIFIER`, a comma separated list of
+/// `mac`, `dbus`, `command`, `bell` and `log`, or `none`.
+/// When it isn't set, notifications go to the desktop if timetrack was built with support for it.
+/// The IDs of actions picked on notifications are sent to `actions`.
+pub fn from_env(actions: Sender<String>) -> Result<Vec<Box<dyn Notifier>>> {
+    match std::env::var("TIMETRACK_NOTIFIER") {
+        Ok(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && *name != "none")
+            .map(|name| notifier_by_name(name, &actions))
+            .collect(),
+        Err(_) => Ok(desktop_notifier(&actions).into_iter().collect()),
+    }
+}
+
+fn notifier_by_name(name: &str, actions: &Sender<String>) -> Result<Box<dyn Notifier>> {
+    match name {
+        "mac" | "dbus" => desktop_notifier(actions)
+            .filter(|_| name == DESKTOP_NOTIFIER_NAME)
+            .ok_or_else(|| {
+                eyre!(
+                    "timetrack was built without {name} notifications. They need the `{feature}` feature, on {platform}",
+                    feature = if name == "mac" {
+                        "mac-notifications"
+                    } else {
+                        "linux-notifications"
+                    },
+                    platform = if name == "mac" { "MacOS" } else { "Linux" },
+                )
+            }),
+        "command" => Ok(Box::new(CommandNotifier::from_env()?)),
+        "bell" => Ok(Box::new(BellNotifier)),
+        "log" => Ok(Box::new(LogFileNotifier::from_env()?)),
+        other => bail!(
+            "unknown notifier \"{other}\" in TIMETRACK_NOTIFIER, expected \"mac\", \"dbus\", \"command\", \"bell\", \"log\" or \"none\""
+        ),
+    }
+}
+
+#[cfg(all(feature = "mac-notifications", target_os = "macos"))]
+const DESKTOP_NOTIFIER_NAME: &str = "mac";
+#[cfg(all(feature = "linux-notifications", target_os = "linux"))]
+const DESKTOP_NOTIFIER_NAME: &str = "dbus";
+#[cfg(not(any(
+    all(feature = "mac-notifications", target_os = "macos"),
+    all(feature = "linux-notifications", target_os = "linux")
+)))]
+const DESKTOP_NOTIFIER_NAME: &str = "";
+
+/// The notifier for this platform's desktop, if timetrack was built with support for it
+#[allow(unused_variables)]
+fn desktop_notifier(actions: &Sender<String>) -> Option<Box<dyn Notifier>> {
+    #[cfg(all(feature = "mac-notifications", target_os = "macos"))]
+    return Some(Box::new(mac::MacNotifier));
+    #[cfg(all(feature = "linux-notifications", target_os = "linux"))]
+    return Some(Box::new(dbus::DbusNotifier::new(actions.clone())));
+    #[allow(unreachable_code)]
+    None
+}
+
+/// Runs the shell command in `TIMETRACK_NOTIFY_COMMAND`.
+/// The title, subtitle and body are passed to it as `$1`, `$2` and `$3`,
+/// and as `TIMETRACK_TITLE`, `TIMETRACK_SUBTITLE` and `TIMETRACK_BODY`.
+#[derive(Debug)]
+pub struct CommandNotifier {
+    command: String,
+}
+impl CommandNotifier {
+    fn from_env() -> Result<Self> {
+        let command = std::env::var("TIMETRACK_NOTIFY_COMMAND").map_err(|_| {
+            eyre!(
+                "the command notifier needs TIMETRACK_NOTIFY_COMMAND to be set to a shell command"
+            )
+        })?;
+        Ok(Self { command })
+    }
+}
+impl Notifier for CommandNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .arg("timetrack")
+            .args([
+                &notification.title,
+                &notification.subtitle,
+                &notification.body,
+            ])
+            .env("TIMETRACK_TITLE", &notification.title)
+            .env("TIMETRACK_SUBTITLE", &notification.subtitle)
+            .env("TIMETRACK_BODY", &notification.body)
+            // the command's output would be drawn over the TUI
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?;
+        if !status.success() {
+            bail!("the notification command failed with {status}");
+        }
+        Ok(())
+    }
+}
+
+/// Rings the terminal bell, and sends an OSC 9 escape sequence,
+/// which some terminals show as a desktop notification
+#[derive(Debug)]
+pub struct BellNotifier;
+impl Notifier for BellNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        let mut stdout = std::io::stdout();
+        write!(
+            stdout,
+            "\x07\x1b]9;{}: {}\x07",
+            notification.title, notification.body
+        )?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+/// Appends notifications to the file in `TIMETRACK_NOTIFY_LOG`,
+/// which defaults to `$HOME/.timetrack/notifications.log`
+#[derive(Debug)]
+pub struct LogFileNotifier {
+    path: PathBuf,
+}
+impl LogFileNotifier {
+    fn from_env() -> Result<Self> {
+        let path = match std::env::var("TIMETRACK_NOTIFY_LOG") {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => PathBuf::from(std::env::var("HOME")?)
+                .join(".timetrack")
+                .join("notifications.log"),
+        };
+        Ok(Self { path })
+    }
+}
+impl Notifier for LogFileNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}",
+            Local::now().to_rfc3339(),
+            notification.title,
+            notification.subtitle,
+            notification.body
+        )?;
+        Ok(())
+    }
+}
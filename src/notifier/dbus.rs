@@ -0,0 +1,343 @@
+//! Notifications through the freedesktop notification service on the session bus,
+//! which is provided by most Linux desktops
+
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use color_eyre::eyre::eyre;
+use zbus::blocking::proxy::SignalIterator;
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::Value;
+
+use super::{Notification, Notifier};
+
+const DESTINATION: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+
+/// Sends notifications to the notification service on the session bus,
+/// and sends the IDs of the actions picked on them to `actions`
+#[derive(Debug)]
+pub struct DbusNotifier {
+    actions: Sender<String>,
+    /// Opened by the first notification, rather than at startup, since there might be no session bus
+    connection: OnceCell<Connection>,
+    /// The notifications with actions which haven't been closed yet
+    open: Arc<Mutex<HashSet<u32>>>,
+}
+impl DbusNotifier {
+    pub fn new(actions: Sender<String>) -> Self {
+        Self {
+            actions,
+            connection: OnceCell::new(),
+            open: Arc::default(),
+        }
+    }
+
+    /// The connection to the session bus, which is opened along with the thread listening for actions
+    fn connection(&self) -> zbus::Result<&Connection> {
+        if let Some(connection) = self.connection.get() {
+            return Ok(connection);
+        }
+        let connection = Connection::session()?;
+        // this subscribes before any notification is sent, so that no click can be missed
+        let signals =
+            Proxy::new(&connection, DESTINATION, PATH, INTERFACE)?.receive_all_signals()?;
+        let (open, actions) = (self.open.clone(), self.actions.clone());
+        std::thread::spawn(move || listen_for_actions(signals, &open, &actions));
+        Ok(self.connection.get_or_init(|| connection))
+    }
+}
+impl Notifier for DbusNotifier {
+    fn notify(&self, notification: &Notification) -> color_eyre::Result<()> {
+        let connection = self.connection()?;
+        let actions: Vec<(&str, &str)> = notification
+            .actions
+            .iter()
+            .map(|action| (action.id.as_str(), action.label.as_str()))
+            .collect();
+        if actions.is_empty() {
+            notify(connection, &notification.title, &notification.body, &[])?;
+            return Ok(());
+        }
+        // the listener can't look at a click until the notification's ID is added
+        let mut open = self
+            .open
+            .lock()
+            .map_err(|_| eyre!("the thread listening for notification actions panicked"))?;
+        let id = notify(
+            connection,
+            &notification.title,
+            &notification.body,
+            &actions,
+        )?;
+        open.insert(id);
+        Ok(())
+    }
+}
+
+/// Sends a notification with `org.freedesktop.Notifications.Notify`, returning its ID.
+/// `actions` are the IDs and labels of the buttons to show on it.
+pub fn notify(
+    connection: &Connection,
+    summary: &str,
+    body: &str,
+    actions: &[(&str, &str)],
+) -> zbus::Result<u32> {
+    let actions: Vec<&str> = actions
+        .iter()
+        .flat_map(|(id, label)| [*id, *label])
+        .collect();
+    let hints: HashMap<&str, Value> = HashMap::new();
+    let reply = connection.call_method(
+        Some(DESTINATION),
+        PATH,
+        Some(INTERFACE),
+        "Notify",
+        &(
+            "timetrack",
+            0u32,
+            "",
+            summary,
+            body,
+            actions,
+            hints,
+            // let the notification server decide how long to show it for
+            -1i32,
+        ),
+    )?;
+    reply.body().deserialize()
+}
+
+/// Sends the actions picked on the notifications in `open` to `actions`,
+/// and forgets each notification once it is closed, by the user or because it timed out.
+/// Returns once nothing is listening to `actions` any more.
+pub fn listen_for_actions(
+    signals: SignalIterator,
+    open: &Mutex<HashSet<u32>>,
+    actions: &Sender<String>,
+) {
+    let close = |id| open.lock().is_ok_and(|mut open| open.remove(&id));
+    for message in signals {
+        let header = message.header();
+        match header.member().map(|member| member.as_str()) {
+            Some("ActionInvoked") => {
+                if let Ok((id, action)) = message.body().deserialize::<(u32, String)>()
+                    && close(id)
+                    && actions.send(action).is_err()
+                {
+                    return;
+                }
+            }
+            Some("NotificationClosed") => {
+                if let Ok((id, _reason)) = message.body().deserialize::<(u32, u32)>() {
+                    close(id);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader};
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use zbus::blocking::connection;
+    use zbus::zvariant::OwnedValue;
+
+    use super::*;
+
+    /// A session bus which only exists for the duration of a test
+    struct PrivateBus {
+        daemon: Child,
+        address: String,
+        dir: PathBuf,
+    }
+    impl PrivateBus {
+        /// Returns `None` if `dbus-daemon` isn't installed
+        fn start() -> Option<Self> {
+            // tests run in parallel, so each bus needs a directory of its own
+            static BUSES: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "timetrack-dbus-{}-{}",
+                std::process::id(),
+                BUSES.fetch_add(1, Ordering::SeqCst)
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            let config = dir.join("session.conf");
+            std::fs::write(
+                &config,
+                format!(
+                    r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:dir={dir}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>"#,
+                    dir = dir.display()
+                ),
+            )
+            .unwrap();
+            let mut daemon = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .arg("--nofork")
+                .arg("--print-address")
+                .stdout(Stdio::piped())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+            Some(Self {
+                daemon,
+                address: address.trim().to_string(),
+                dir,
+            })
+        }
+
+        fn connect(&self) -> connection::Builder<'_> {
+            connection::Builder::address(self.address.as_str()).unwrap()
+        }
+    }
+    impl Drop for PrivateBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// Records the notifications it receives instead of showing them
+    struct FakeNotificationServer {
+        received: Arc<Mutex<Vec<(String, String, String)>>>,
+    }
+    #[zbus::interface(name = "org.freedesktop.Notifications")]
+    impl FakeNotificationServer {
+        #[allow(clippy::too_many_arguments)]
+        fn notify(
+            &self,
+            app_name: String,
+            _replaces_id: u32,
+            _app_icon: String,
+            summary: String,
+            body: String,
+            _actions: Vec<String>,
+            _hints: HashMap<String, OwnedValue>,
+            _expire_timeout: i32,
+        ) -> u32 {
+            let mut received = self.received.lock().unwrap();
+            received.push((app_name, summary, body));
+            received.len() as u32
+        }
+    }
+
+    fn serve(bus: &PrivateBus, received: Arc<Mutex<Vec<(String, String, String)>>>) -> Connection {
+        bus.connect()
+            .name(DESTINATION)
+            .unwrap()
+            .serve_at(PATH, FakeNotificationServer { received })
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn notifications_are_sent_to_the_notification_service() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("skipping: dbus-daemon is not installed");
+            return;
+        };
+        let received = Arc::new(Mutex::new(vec![]));
+        let _server = serve(&bus, received.clone());
+
+        let client = bus.connect().build().unwrap();
+        let id = notify(
+            &client,
+            "Pomodoro Session Over!!",
+            "You've worked for 25min on reading!",
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(id, 1);
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![(
+                "timetrack".to_string(),
+                "Pomodoro Session Over!!".to_string(),
+                "You've worked for 25min on reading!".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn picked_actions_are_sent_once() {
+        let Some(bus) = PrivateBus::start() else {
+            eprintln!("skipping: dbus-daemon is not installed");
+            return;
+        };
+        let server = serve(&bus, Arc::new(Mutex::new(vec![])));
+
+        let client = bus.connect().build().unwrap();
+        let signals = Proxy::new(&client, DESTINATION, PATH, INTERFACE)
+            .unwrap()
+            .receive_all_signals()
+            .unwrap();
+        let id = notify(
+            &client,
+            "Pomodoro Time's Up!!",
+            "Keep going on reading for a few more minutes, or finish the session.",
+            &[("extend-5", "+5min"), ("finish", "Finish")],
+        )
+        .unwrap();
+        // another notification, which times out without a click
+        let expired = id + 2;
+        let open = Arc::new(Mutex::new(HashSet::from([id, expired])));
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn({
+            let open = open.clone();
+            move || listen_for_actions(signals, &open, &sender)
+        });
+        // a click on another notification shouldn't count, and neither should a second click
+        for (notification_id, action) in [(id + 1, "finish"), (id, "extend-5"), (id, "finish")] {
+            server
+                .emit_signal(
+                    None::<()>,
+                    PATH,
+                    INTERFACE,
+                    "ActionInvoked",
+                    &(notification_id, action),
+                )
+                .unwrap();
+        }
+
+        server
+            .emit_signal(
+                None::<()>,
+                PATH,
+                INTERFACE,
+                "NotificationClosed",
+                &(expired, 1u32),
+            )
+            .unwrap();
+
+        let timeout = std::time::Duration::from_secs(5);
+        assert_eq!(receiver.recv_timeout(timeout), Ok("extend-5".to_string()));
+        assert!(receiver.recv_timeout(timeout / 10).is_err());
+        assert!(open.lock().unwrap().is_empty());
+    }
+}
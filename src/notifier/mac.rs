@@ -0,0 +1,24 @@
+use color_eyre::Result;
+use color_eyre::eyre::eyre;
+
+use super::{Notification, Notifier};
+
+/// Shows notifications in the MacOS notification centre
+#[derive(Debug)]
+pub struct MacNotifier;
+impl Notifier for MacNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        mac_notification_sys::send_notification(
+            &notification.title,
+            Some(notification.subtitle.as_str()),
+            &notification.body,
+            Some(
+                mac_notification_sys::Notification::new()
+                    .asynchronous(true)
+                    .wait_for_click(true),
+            ),
+        )
+        .map_err(|error| eyre!("couldn't send a notification: {error}"))?;
+        Ok(())
+    }
+}
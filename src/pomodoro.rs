@@ -0,0 +1,141 @@
+//! Pomodoro cycles: work blocks separated by short breaks, with a long break every few work blocks.
+//! The lengths of the breaks are read from `TIMETRACK_SHORT_BREAK`, `TIMETRACK_LONG_BREAK`
+//! and `TIMETRACK_LONG_BREAK_EVERY`.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use color_eyre::Result;
+use color_eyre::eyre::eyre;
+use serde::{Deserialize, Serialize};
+
+use crate::state::ActivityId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PomodoroSettings {
+    pub short_break_minutes: usize,
+    pub long_break_minutes: usize,
+    /// How many work blocks there are in a cycle, the last of which is followed by a long break
+    pub long_break_every: usize,
+}
+impl Default for PomodoroSettings {
+    fn default() -> Self {
+        Self {
+            short_break_minutes: 5,
+            long_break_minutes: 15,
+            long_break_every: 4,
+        }
+    }
+}
+impl PomodoroSettings {
+    pub fn from_env() -> Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            short_break_minutes: minutes_from_env(
+                "TIMETRACK_SHORT_BREAK",
+                default.short_break_minutes,
+            )?,
+            long_break_minutes: minutes_from_env(
+                "TIMETRACK_LONG_BREAK",
+                default.long_break_minutes,
+            )?,
+            long_break_every: minutes_from_env(
+                "TIMETRACK_LONG_BREAK_EVERY",
+                default.long_break_every,
+            )?
+            .max(1),
+        })
+    }
+}
+
+fn minutes_from_env(name: &str, default: usize) -> Result<usize> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| eyre!("{name} should be a whole number, but it was \"{value}\"")),
+        Err(_) => Ok(default),
+    }
+}
+
+/// A run of pomodoros, which lasts until a work block or a break is ended by hand
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PomodoroCycle {
+    /// The length of each work block
+    pub work_minutes: usize,
+    /// The number of work blocks finished since the last long break
+    pub completed: usize,
+    /// The ongoing break, if the cycle is between two work blocks
+    pub on_break: Option<PomodoroBreak>,
+}
+impl PomodoroCycle {
+    pub fn new(work_minutes: usize) -> Self {
+        Self {
+            work_minutes,
+            completed: 0,
+            on_break: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PomodoroBreak {
+    /// The activity which was worked on before the break
+    pub activity_id: ActivityId,
+    pub start_time: DateTime<Utc>,
+    pub minutes: usize,
+    pub long: bool,
+}
+impl PomodoroBreak {
+    pub fn end_time(&self) -> DateTime<Utc> {
+        self.start_time + TimeDelta::minutes(self.minutes as i64)
+    }
+}
+
+/// What to do with a pomodoro whose timer is up, picked in the Ongoing widget or on its notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeUpAction {
+    /// Keeps working for this many more minutes, as part of the same session
+    Extend(usize),
+    Finish,
+}
+impl TimeUpAction {
+    pub const ALL: [TimeUpAction; 4] = [
+        TimeUpAction::Extend(5),
+        TimeUpAction::Extend(10),
+        TimeUpAction::Extend(15),
+        TimeUpAction::Finish,
+    ];
+
+    pub fn label(self) -> String {
+        match self {
+            TimeUpAction::Extend(minutes) => format!("+{minutes}min"),
+            TimeUpAction::Finish => "Finish".to_string(),
+        }
+    }
+
+    /// The key which picks this action in the TUI
+    pub fn keybind(self) -> char {
+        match self {
+            TimeUpAction::Extend(5) => 'a',
+            TimeUpAction::Extend(10) => 's',
+            TimeUpAction::Extend(_) => 'd',
+            TimeUpAction::Finish => 'f',
+        }
+    }
+
+    /// How this action is identified on notifications
+    pub fn id(self) -> String {
+        match self {
+            TimeUpAction::Extend(minutes) => format!("extend-{minutes}"),
+            TimeUpAction::Finish => "finish".to_string(),
+        }
+    }
+
+    pub fn from_keybind(keybind: char) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.keybind() == keybind)
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.id() == id)
+    }
+}
@@ -0,0 +1,467 @@
+//! Strategies for picking the activity to work on next.
+//! The strategy in use is stored in the state, and can be switched from the Track window or with `timetrack strategy`.
+
+use std::cmp::{Ordering, Reverse};
+use std::fmt::Debug;
+
+use chrono::{DateTime, Local, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::state::Activity;
+
+/// An activity which still has time left today
+#[derive(Debug, Clone, Copy)]
+pub struct Candidate<'a> {
+    pub activity: &'a Activity,
+    /// Where the activity is in the list of activities
+    pub position: usize,
+    pub acheived_minutes: usize,
+    /// When the last session of the activity today ended
+    pub last_worked: Option<DateTime<Utc>>,
+}
+impl Candidate<'_> {
+    pub fn remaining_minutes(&self) -> usize {
+        self.activity
+            .today_target_minutes()
+            .saturating_sub(self.acheived_minutes)
+    }
+
+    /// How much of the target has been acheived, from 0 to 1
+    pub fn ratio(&self) -> f64 {
+        self.acheived_minutes as f64 / self.activity.today_target_minutes().max(1) as f64
+    }
+
+    /// Everything a strategy could rank the candidate by, for showing next to its reason
+    pub fn details(&self) -> String {
+        let mut details = format!(
+            "{}min left, {:.0}% done, {}",
+            self.remaining_minutes(),
+            self.ratio() * 100.0,
+            self.last_worked_description()
+        );
+        if self.activity.priority() > 0 {
+            details.push_str(&format!(", priority {}", self.activity.priority()));
+        }
+        details
+    }
+
+    fn last_worked_description(&self) -> String {
+        match self.last_worked {
+            Some(time) => format!(
+                "last worked on at {}",
+                time.with_timezone(&Local).format("%H:%M")
+            ),
+            None => "not worked on yet today".to_string(),
+        }
+    }
+}
+
+/// A candidate, and why it was ranked where it is
+#[derive(Debug, Clone)]
+pub struct Recommendation<'a> {
+    pub candidate: Candidate<'a>,
+    pub reason: String,
+}
+
+/// What a strategy knows about today besides the candidates
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Context {
+    /// The position of the activity which was worked on most recently today
+    pub last_worked: Option<usize>,
+    /// Stays the same until time is logged, so that random choices don't change on every redraw
+    pub seed: u64,
+}
+
+pub trait RecommendationStrategy: Debug {
+    /// Orders the indices of `candidates` from the best one to work on next to the worst.
+    /// `candidates` are in the order the activities are listed in.
+    fn rank(&self, candidates: &[Candidate], context: &Context) -> Vec<usize>;
+
+    /// Why the candidate at `index` is ranked where it is
+    fn reason(&self, index: usize, candidates: &[Candidate], context: &Context) -> String;
+}
+
+/// Ranks `candidates` with `strategy`, best first
+pub fn recommend<'a>(
+    strategy: &dyn RecommendationStrategy,
+    candidates: &[Candidate<'a>],
+    context: &Context,
+) -> Vec<Recommendation<'a>> {
+    strategy
+        .rank(candidates, context)
+        .into_iter()
+        .map(|i| Recommendation {
+            candidate: candidates[i],
+            reason: strategy.reason(i, candidates, context),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum StrategyKind {
+    /// The activity with the least time spent on it today
+    #[default]
+    LowestTime,
+    /// The activity with the smallest share of its target done
+    LowestRatio,
+    /// The activity with the most time left to reach its target
+    LargestRemaining,
+    /// The next activity in the list after the one worked on last
+    RoundRobin,
+    /// A random activity, where more time left makes it more likely
+    WeightedRandom,
+    /// The activity with the highest priority, then the least time spent on it
+    Priority,
+}
+impl StrategyKind {
+    pub const ALL: [StrategyKind; 6] = [
+        StrategyKind::LowestTime,
+        StrategyKind::LowestRatio,
+        StrategyKind::LargestRemaining,
+        StrategyKind::RoundRobin,
+        StrategyKind::WeightedRandom,
+        StrategyKind::Priority,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StrategyKind::LowestTime => "lowest time",
+            StrategyKind::LowestRatio => "lowest ratio",
+            StrategyKind::LargestRemaining => "largest remaining",
+            StrategyKind::RoundRobin => "round robin",
+            StrategyKind::WeightedRandom => "weighted random",
+            StrategyKind::Priority => "priority",
+        }
+    }
+
+    /// The strategy after this one, for cycling through them with a single key
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|kind| *kind == self).unwrap_or(0);
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+
+    pub fn strategy(self) -> &'static dyn RecommendationStrategy {
+        match self {
+            StrategyKind::LowestTime => &LowestTime,
+            StrategyKind::LowestRatio => &LowestRatio,
+            StrategyKind::LargestRemaining => &LargestRemaining,
+            StrategyKind::RoundRobin => &RoundRobin,
+            StrategyKind::WeightedRandom => &WeightedRandom,
+            StrategyKind::Priority => &Priority,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LowestTime;
+impl RecommendationStrategy for LowestTime {
+    fn rank(&self, candidates: &[Candidate], _context: &Context) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..candidates.len()).collect();
+        // ties go to the activity furthest down the list
+        ranking.sort_by_key(|i| (candidates[*i].acheived_minutes, Reverse(*i)));
+        ranking
+    }
+
+    fn reason(&self, index: usize, candidates: &[Candidate], _context: &Context) -> String {
+        format!("{}min done today", candidates[index].acheived_minutes)
+    }
+}
+
+#[derive(Debug)]
+pub struct LowestRatio;
+impl RecommendationStrategy for LowestRatio {
+    fn rank(&self, candidates: &[Candidate], _context: &Context) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..candidates.len()).collect();
+        ranking.sort_by(|a, b| {
+            candidates[*a]
+                .ratio()
+                .partial_cmp(&candidates[*b].ratio())
+                .unwrap_or(Ordering::Equal)
+        });
+        ranking
+    }
+
+    fn reason(&self, index: usize, candidates: &[Candidate], _context: &Context) -> String {
+        format!(
+            "{:.0}% of the target done",
+            candidates[index].ratio() * 100.0
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct LargestRemaining;
+impl RecommendationStrategy for LargestRemaining {
+    fn rank(&self, candidates: &[Candidate], _context: &Context) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..candidates.len()).collect();
+        // ties go to the activity furthest up the list
+        ranking.sort_by_key(|i| Reverse(candidates[*i].remaining_minutes()));
+        ranking
+    }
+
+    fn reason(&self, index: usize, candidates: &[Candidate], _context: &Context) -> String {
+        format!("{}min left", candidates[index].remaining_minutes())
+    }
+}
+
+#[derive(Debug)]
+pub struct RoundRobin;
+impl RecommendationStrategy for RoundRobin {
+    fn rank(&self, candidates: &[Candidate], context: &Context) -> Vec<usize> {
+        // the activities after the last one come first, then the list wraps around
+        let (after, before): (Vec<usize>, Vec<usize>) = (0..candidates.len()).partition(|i| {
+            context
+                .last_worked
+                .is_none_or(|last_worked| candidates[*i].position > last_worked)
+        });
+        after.into_iter().chain(before).collect()
+    }
+
+    fn reason(&self, index: usize, candidates: &[Candidate], _context: &Context) -> String {
+        candidates[index].last_worked_description()
+    }
+}
+
+#[derive(Debug)]
+pub struct WeightedRandom;
+impl RecommendationStrategy for WeightedRandom {
+    fn rank(&self, candidates: &[Candidate], context: &Context) -> Vec<usize> {
+        let mut rng = fastrand::Rng::with_seed(context.seed);
+        let mut left: Vec<usize> = (0..candidates.len()).collect();
+        let mut ranking = vec![];
+        // each pick is made from the candidates which haven't been picked yet
+        while !left.is_empty() {
+            let total: usize = left
+                .iter()
+                .map(|i| candidates[*i].remaining_minutes())
+                .sum();
+            let mut roll = rng.usize(0..total.max(1));
+            let pick = left
+                .iter()
+                .position(|i| {
+                    let remaining_minutes = candidates[*i].remaining_minutes();
+                    if roll < remaining_minutes {
+                        true
+                    } else {
+                        roll -= remaining_minutes;
+                        false
+                    }
+                })
+                .unwrap_or(0);
+            ranking.push(left.remove(pick));
+        }
+        ranking
+    }
+
+    fn reason(&self, index: usize, candidates: &[Candidate], _context: &Context) -> String {
+        let total: usize = candidates.iter().map(Candidate::remaining_minutes).sum();
+        format!(
+            "{}min left, a {}% chance",
+            candidates[index].remaining_minutes(),
+            candidates[index].remaining_minutes() * 100 / total.max(1)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Priority;
+impl RecommendationStrategy for Priority {
+    fn rank(&self, candidates: &[Candidate], _context: &Context) -> Vec<usize> {
+        let mut ranking: Vec<usize> = (0..candidates.len()).collect();
+        ranking.sort_by_key(|i| {
+            (
+                Reverse(candidates[*i].activity.priority()),
+                candidates[*i].acheived_minutes,
+            )
+        });
+        ranking
+    }
+
+    fn reason(&self, index: usize, candidates: &[Candidate], _context: &Context) -> String {
+        format!(
+            "priority {}, {}min done today",
+            candidates[index].activity.priority(),
+            candidates[index].acheived_minutes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ActivityId;
+
+    /// The index of the candidate ranked first
+    fn choose(
+        strategy: &dyn RecommendationStrategy,
+        candidates: &[Candidate],
+        context: &Context,
+    ) -> Option<usize> {
+        strategy.rank(candidates, context).first().copied()
+    }
+
+    fn activities(targets: &[(usize, usize)]) -> Vec<Activity> {
+        targets
+            .iter()
+            .enumerate()
+            .map(|(i, (target_minutes, priority))| {
+                let mut activity =
+                    Activity::new(ActivityId::from_raw(i + 1), format!("{i}"), *target_minutes);
+                activity.set_priority(*priority);
+                activity
+            })
+            .collect()
+    }
+
+    fn candidates<'a>(activities: &'a [Activity], acheived: &[usize]) -> Vec<Candidate<'a>> {
+        activities
+            .iter()
+            .zip(acheived)
+            .enumerate()
+            .map(|(position, (activity, acheived_minutes))| Candidate {
+                activity,
+                position,
+                acheived_minutes: *acheived_minutes,
+                last_worked: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lowest_time_picks_the_least_worked_on() {
+        let activities = activities(&[(15, 0), (240, 0), (60, 0)]);
+        let candidates = candidates(&activities, &[10, 20, 5]);
+        assert_eq!(
+            choose(&LowestTime, &candidates, &Context::default()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn lowest_ratio_accounts_for_the_size_of_targets() {
+        let activities = activities(&[(15, 0), (240, 0)]);
+        let candidates = candidates(&activities, &[10, 20]);
+        assert_eq!(
+            choose(&LowestRatio, &candidates, &Context::default()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn largest_remaining_picks_the_most_time_left() {
+        let activities = activities(&[(60, 0), (240, 0), (120, 0)]);
+        let candidates = candidates(&activities, &[0, 200, 30]);
+        assert_eq!(
+            choose(&LargestRemaining, &candidates, &Context::default()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn round_robin_moves_on_from_the_last_activity() {
+        let activities = activities(&[(60, 0), (60, 0), (60, 0)]);
+        let all = candidates(&activities, &[0, 0, 0]);
+        let context = |last_worked| Context {
+            last_worked,
+            seed: 0,
+        };
+        assert_eq!(choose(&RoundRobin, &all, &context(None)), Some(0));
+        assert_eq!(choose(&RoundRobin, &all, &context(Some(0))), Some(1));
+        assert_eq!(choose(&RoundRobin, &all, &context(Some(2))), Some(0));
+        // finished activities aren't candidates, so they are skipped
+        let without_second = [all[0], all[2]];
+        assert_eq!(
+            choose(&RoundRobin, &without_second, &context(Some(0))),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn weighted_random_is_stable_and_follows_the_weights() {
+        let activities = activities(&[(1, 0), (1000, 0)]);
+        let candidates = candidates(&activities, &[0, 0]);
+        let picks: Vec<_> = (0..100)
+            .map(|seed| {
+                choose(
+                    &WeightedRandom,
+                    &candidates,
+                    &Context {
+                        last_worked: None,
+                        seed,
+                    },
+                )
+            })
+            .collect();
+        assert!(picks.iter().all(Option::is_some));
+        assert!(picks.iter().filter(|pick| **pick == Some(1)).count() > 90);
+        for seed in 0..10 {
+            let context = Context {
+                last_worked: None,
+                seed,
+            };
+            assert_eq!(
+                choose(&WeightedRandom, &candidates, &context),
+                choose(&WeightedRandom, &candidates, &context)
+            );
+        }
+    }
+
+    #[test]
+    fn priority_comes_before_time_spent() {
+        let activities = activities(&[(60, 0), (60, 2), (60, 2)]);
+        let candidates = candidates(&activities, &[0, 30, 10]);
+        assert_eq!(choose(&Priority, &candidates, &Context::default()), Some(2));
+    }
+
+    #[test]
+    fn strategies_have_no_choice_without_candidates() {
+        for kind in StrategyKind::ALL {
+            assert_eq!(choose(kind.strategy(), &[], &Context::default()), None);
+        }
+    }
+
+    #[test]
+    fn rankings_include_every_candidate_once() {
+        let activities = activities(&[(60, 1), (30, 0), (90, 2), (45, 0)]);
+        let candidates = candidates(&activities, &[10, 0, 30, 5]);
+        let context = Context {
+            last_worked: Some(1),
+            seed: 7,
+        };
+        for kind in StrategyKind::ALL {
+            let mut ranking = kind.strategy().rank(&candidates, &context);
+            assert_eq!(
+                ranking.first().copied(),
+                choose(kind.strategy(), &candidates, &context)
+            );
+            ranking.sort();
+            assert_eq!(ranking, vec![0, 1, 2, 3], "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn round_robin_ranks_in_turn() {
+        let activities = activities(&[(60, 0), (60, 0), (60, 0)]);
+        let candidates = candidates(&activities, &[0, 0, 0]);
+        let context = Context {
+            last_worked: Some(1),
+            seed: 0,
+        };
+        assert_eq!(RoundRobin.rank(&candidates, &context), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn recommendations_explain_the_ranking() {
+        let activities = activities(&[(15, 0), (240, 0)]);
+        let candidates = candidates(&activities, &[10, 20]);
+        let recommendations = recommend(&LowestRatio, &candidates, &Context::default());
+        assert_eq!(
+            recommendations
+                .iter()
+                .map(|x| (x.candidate.position, x.reason.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(1, "8% of the target done"), (0, "67% of the target done")]
+        );
+    }
+}
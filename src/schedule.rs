@@ -0,0 +1,195 @@
+//! Which days an activity has a target on, and how big each day's target is.
+//! The target for today is worked out when the day is refreshed, and stored on the activity,
+//! along with the balance carried over from the previous day.
+
+use std::fmt::Display;
+
+use chrono::{Datelike, NaiveDate, TimeDelta, Weekday};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Schedule {
+    /// The activity's target, every day
+    #[default]
+    Daily,
+    /// A target for each day of the week, from Monday to Sunday, where 0 is a day off
+    Weekdays([usize; 7]),
+    /// A target for the whole week, spread evenly over the days left in it
+    Weekly(usize),
+}
+impl Schedule {
+    pub fn is_daily(&self) -> bool {
+        *self == Schedule::Daily
+    }
+
+    /// True if the schedule has no target on `date`'s day of the week, whatever was worked before it
+    pub fn is_day_off(&self, date: NaiveDate) -> bool {
+        match self {
+            Schedule::Weekdays(targets) => {
+                targets[date.weekday().num_days_from_monday() as usize] == 0
+            }
+            Schedule::Daily | Schedule::Weekly(_) => false,
+        }
+    }
+
+    /// The target on `date`, where `daily_minutes` is the activity's everyday target
+    /// and `done_this_week` is the time spent on it earlier in the week
+    pub fn target_on(&self, date: NaiveDate, daily_minutes: usize, done_this_week: usize) -> usize {
+        let day = date.weekday().num_days_from_monday() as usize;
+        match self {
+            Schedule::Daily => daily_minutes,
+            Schedule::Weekdays(targets) => targets[day],
+            Schedule::Weekly(minutes) => minutes.saturating_sub(done_this_week).div_ceil(7 - day),
+        }
+    }
+}
+impl Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::Daily => write!(f, "daily"),
+            Schedule::Weekdays(targets) => {
+                let days: Vec<String> = targets
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, minutes)| **minutes > 0)
+                    .map(|(day, minutes)| {
+                        format!(
+                            "{}={minutes}",
+                            Weekday::try_from(day as u8)
+                                .unwrap()
+                                .to_string()
+                                .to_lowercase()
+                        )
+                    })
+                    .collect();
+                if days.is_empty() {
+                    write!(f, "no days")
+                } else {
+                    write!(f, "{}", days.join(" "))
+                }
+            }
+            Schedule::Weekly(minutes) => write!(f, "weekly {minutes}min"),
+        }
+    }
+}
+
+/// Opts an activity into carrying the time it falls short of its target into the next day
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CarryOver {
+    /// Also carries time worked beyond the target, which lowers the next day's target
+    pub surplus: bool,
+    /// The most the balance can reach, either way
+    pub cap_minutes: usize,
+    /// The percentage of the balance which is forgiven every day
+    pub decay_percent: u8,
+}
+impl CarryOver {
+    /// The balance for the next day, where a positive balance is time owed and a negative one is time
+    /// worked ahead. `balance` is the one the day started with, which was added to `scheduled_minutes`.
+    pub fn carry(&self, balance: i64, scheduled_minutes: usize, acheived_minutes: usize) -> i64 {
+        let owed = balance + scheduled_minutes as i64 - acheived_minutes as i64;
+        let decayed = owed * (100 - self.decay_percent.min(100) as i64) / 100;
+        let floor = if self.surplus {
+            -(self.cap_minutes as i64)
+        } else {
+            0
+        };
+        decayed.clamp(floor, self.cap_minutes as i64)
+    }
+}
+
+/// The Monday of the week which `date` is in
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - TimeDelta::days(date.weekday().num_days_from_monday() as i64)
+}
+
+/// Parses a day's target written like `mon=60`
+pub fn parse_day_target(day_target: &str) -> Result<(Weekday, usize), String> {
+    let (day, minutes) = day_target.split_once('=').ok_or_else(|| {
+        format!("expected a day and a target like mon=60, but got \"{day_target}\"")
+    })?;
+    let day: Weekday = day
+        .parse()
+        .map_err(|_| format!("\"{day}\" is not a day of the week"))?;
+    let minutes = minutes
+        .parse()
+        .map_err(|_| format!("\"{minutes}\" is not a whole number of minutes"))?;
+    Ok((day, minutes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        // 2025-06-02 is a Monday
+        NaiveDate::from_ymd_opt(2025, 6, day).unwrap()
+    }
+
+    #[test]
+    fn weekdays_have_their_own_targets() {
+        let schedule = Schedule::Weekdays([60, 0, 60, 0, 60, 0, 0]);
+        let targets: Vec<usize> = (2..9)
+            .map(|day| schedule.target_on(date(day), 30, 0))
+            .collect();
+        assert_eq!(targets, vec![60, 0, 60, 0, 60, 0, 0]);
+        assert_eq!(schedule.to_string(), "mon=60 wed=60 fri=60");
+    }
+
+    #[test]
+    fn weekly_targets_are_spread_over_the_days_left() {
+        let schedule = Schedule::Weekly(300);
+        assert_eq!(schedule.target_on(date(2), 30, 0), 43);
+        // Thursday, with 4 days left and 100 minutes done
+        assert_eq!(schedule.target_on(date(5), 30, 100), 50);
+        assert_eq!(schedule.target_on(date(8), 30, 400), 0);
+    }
+
+    #[test]
+    fn carry_over_is_capped_and_decays() {
+        let carry_over = CarryOver {
+            surplus: false,
+            cap_minutes: 60,
+            decay_percent: 0,
+        };
+        assert_eq!(carry_over.carry(0, 60, 30), 30);
+        // the balance was part of the day's target, so it is only owed again if it wasn't worked off
+        assert_eq!(carry_over.carry(30, 60, 60), 30);
+        assert_eq!(carry_over.carry(30, 60, 90), 0);
+        assert_eq!(carry_over.carry(50, 60, 0), 60);
+        assert_eq!(carry_over.carry(0, 60, 100), 0);
+        let decaying = CarryOver {
+            decay_percent: 50,
+            ..carry_over
+        };
+        assert_eq!(decaying.carry(0, 60, 20), 20);
+    }
+
+    #[test]
+    fn surplus_is_only_carried_when_opted_into() {
+        let carry_over = CarryOver {
+            surplus: true,
+            cap_minutes: 30,
+            decay_percent: 0,
+        };
+        assert_eq!(carry_over.carry(0, 60, 80), -20);
+        assert_eq!(carry_over.carry(-20, 60, 100), -30);
+        assert_eq!(carry_over.carry(-20, 60, 40), 0);
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        assert_eq!(week_start(date(2)), date(2));
+        assert_eq!(week_start(date(8)), date(2));
+        assert_eq!(week_start(date(9)), date(9));
+    }
+
+    #[test]
+    fn day_targets_are_parsed() {
+        assert_eq!(parse_day_target("wed=45"), Ok((Weekday::Wed, 45)));
+        assert_eq!(parse_day_target("Friday=0"), Ok((Weekday::Fri, 0)));
+        assert!(parse_day_target("mon").is_err());
+        assert!(parse_day_target("someday=5").is_err());
+        assert!(parse_day_target("tue=lots").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use chrono::{DateTime, Local, Utc};
+use ratatui::{
+    crossterm::event::{Event, KeyCode, KeyEvent},
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Color, Stylize},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Widget, Wrap},
+};
+
+use crate::{
+    instruction_line,
+    state::{StaleReason, StaleResolution, StaleTime, State},
+};
+
+/// Asks the user what to do about stale time in the ongoing session.
+/// While it is shown, it takes all input.
+#[derive(Debug)]
+pub struct StalePrompt {
+    stale: StaleTime,
+}
+impl StalePrompt {
+    pub fn new(stale: StaleTime) -> Self {
+        Self { stale }
+    }
+
+    /// Returns true once the user has made a decision, and the prompt should be closed
+    pub fn handle_event(&self, state: &mut State, event: &Event) -> bool {
+        let resolution = match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('k'),
+                ..
+            }) => StaleResolution::Keep,
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('t'),
+                ..
+            }) => StaleResolution::Trim,
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('d'),
+                ..
+            }) => StaleResolution::Discard,
+            _ => return false,
+        };
+        state.resolve_stale_time(self.stale, resolution);
+        true
+    }
+
+    pub fn draw(&self, state: &State, frame: &mut ratatui::Frame, area: Rect) {
+        frame.render_widget(
+            &StalePromptWidget {
+                stale: self.stale,
+                activity_name: state
+                    .activity_by_id(self.stale.activity_id)
+                    .map_or("the ongoing activity", |activity| activity.name()),
+            },
+            area,
+        );
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local).format("%H:%M").to_string()
+}
+
+struct StalePromptWidget<'a> {
+    stale: StaleTime,
+    activity_name: &'a str,
+}
+impl<'a> Widget for &StalePromptWidget<'a> {
+    fn render(self, area: Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let [area] = Layout::horizontal([Constraint::Max(70)])
+            .flex(Flex::Center)
+            .areas(area);
+        let [area] = Layout::vertical([Constraint::Length(9)])
+            .flex(Flex::Center)
+            .areas(area);
+
+        let from = format_time(self.stale.from);
+        let to = format_time(self.stale.to);
+        let minutes = (self.stale.to - self.stale.from).num_minutes();
+        let explanation = match self.stale.reason {
+            StaleReason::Gap => format!(
+                "timetrack wasn't running between {from} and {to} ({minutes}min), so your computer may have been asleep."
+            ),
+            StaleReason::LongSession => format!(
+                "This session has been running for a long time. Were you still working on it between {from} and {to} ({minutes}min)?"
+            ),
+        };
+
+        Clear.render(area, buf);
+        Paragraph::new(vec![
+            Line::from(vec![
+                "Still working on ".into(),
+                self.activity_name.green().bold(),
+                "?".into(),
+            ]),
+            Line::from(""),
+            Line::from(explanation),
+        ])
+        .wrap(Wrap { trim: true })
+        .block(
+            Block::new()
+                .title(" Stale Session ")
+                .style(Color::Yellow)
+                .borders(Borders::all())
+                .title_bottom(instruction_line(vec![
+                    ("Keep It", "K"),
+                    (&format!("End at {from}"), "T"),
+                    ("Discard It", "D"),
+                ])),
+        )
+        .render(area, buf);
+    }
+}
@@ -1,27 +1,41 @@
 use std::fmt::Display;
+use std::rc::Rc;
 
-use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeDelta, Utc};
 use ratatui::{style::Stylize, text::Line};
 use serde::{Deserialize, Serialize};
-pub use todos_and_buckets::{Bucket, TodoItem, TodoItemOld};
+pub use todos_and_buckets::{Bucket, TodoItem};
 
-use crate::stored_state_file_path;
+use crate::pomodoro::{PomodoroBreak, PomodoroCycle, PomodoroSettings};
+use crate::recommend::StrategyKind;
+use crate::schedule::{self, CarryOver, Schedule};
+use crate::storage::Storage;
+use crate::{day, migrations};
 
+/// The layout of the state file, as of `migrations::CURRENT_SCHEMA_VERSION`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StateBuilder {
+    pub schema_version: u32,
     pub date: Option<NaiveDate>,
     pub activities: Option<Vec<Activity>>,
+    pub log: Option<Vec<LogEntry>>,
+    pub history: Option<Vec<DayRecord>>,
     pub next_activity_id: Option<usize>,
     pub current: Option<CurrentActionInfo>,
-    /// NOTE: this was used in an older version, before buckets
-    pub todo: Option<Vec<String>>,
-    pub todo_v2: Option<Vec<TodoItemOld>>,
-    pub buckets: Option<Vec<String>>,
-    pub buckets_v2: Option<Vec<Bucket>>,
+    pub buckets: Option<Vec<Bucket>>,
+    pub pomodoro: Option<PomodoroCycle>,
+    pub recommendation_strategy: Option<StrategyKind>,
 }
 
 pub const DEFAULT_BUCKET_NAME: &str = "N/A";
 
+/// A gap this long since the TUI was last seen running means the computer was probably asleep
+const STALE_GAP: TimeDelta = TimeDelta::minutes(15);
+/// Sessions which run for longer than this without being reviewed are probably forgotten
+const MAX_SESSION: TimeDelta = TimeDelta::hours(4);
+/// How often the last seen time needs to be saved
+const LAST_SEEN_INTERVAL: TimeDelta = TimeDelta::minutes(1);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
     date: NaiveDate,
@@ -29,84 +43,184 @@ pub struct State {
     next_activity_id: usize,
     current: Option<CurrentActionInfo>,
     buckets: Vec<Bucket>,
+    log: Vec<LogEntry>,
+    history: Vec<DayRecord>,
+    pomodoro: Option<PomodoroCycle>,
+    /// How the activity to work on next is picked
+    #[serde(default)]
+    recommendation_strategy: StrategyKind,
+    /// True if there are changes which haven't been saved yet
+    #[serde(skip)]
+    dirty: bool,
+    /// True if another instance of the program owns the state file,
+    /// in which case changes are never saved
+    #[serde(skip)]
+    read_only: bool,
+    /// Where the state is saved, which is `None` if it should never be saved
+    #[serde(skip)]
+    storage: Option<Rc<dyn Storage>>,
+    /// The local time at which a new day starts
+    #[serde(skip)]
+    day_start: NaiveTime,
+    #[serde(skip)]
+    pomodoro_settings: PomodoroSettings,
+    /// Operations which can be undone, most recent last. This only lasts until the program exits.
+    #[serde(skip)]
+    undo_stack: Vec<UndoEntry>,
+    /// Operations which were undone, and can be redone, most recent last
+    #[serde(skip)]
+    redo_stack: Vec<UndoEntry>,
+    /// True while an undoable operation is running
+    #[serde(skip)]
+    recording_undo: bool,
 }
 impl From<StateBuilder> for State {
     fn from(value: StateBuilder) -> Self {
-        let mut buckets = value.buckets_v2.unwrap_or_default();
-        for name in value.buckets.unwrap_or_default() {
-            if buckets.iter().map(|x| x.name()).all(|x| x != name.as_str()) {
-                buckets.push(Bucket::new(name, vec![]));
-            }
-        }
+        let mut buckets = value.buckets.unwrap_or_default();
         // REQUIREMENT: `buckets` must contain an `N/A` bucket
-        let default_bucket = if let Some(default_bucket) =
-            buckets.iter_mut().find(|x| x.name() == DEFAULT_BUCKET_NAME)
-        {
-            default_bucket
-        } else {
+        if buckets.iter().all(|x| x.name() != DEFAULT_BUCKET_NAME) {
             buckets.push(Bucket::new(String::from(DEFAULT_BUCKET_NAME), vec![]));
-            let last_index = buckets.len() - 1;
-            &mut buckets[last_index]
-        };
-
-        for old_todo in value.todo.unwrap_or_default() {
-            default_bucket.push_todo(TodoItem::new(old_todo));
-        }
-
-        for todo in value.todo_v2.unwrap_or_default() {
-            if let Some(target_bucket) = buckets.iter_mut().find(|x| {
-                x.name()
-                    == todo
-                        .bucket
-                        .as_ref()
-                        .map(|x| x.as_str())
-                        .unwrap_or(DEFAULT_BUCKET_NAME)
-            }) {
-                target_bucket.push_todo(TodoItem::new(todo.item));
-            }
         }
+        let activities = value.activities.unwrap_or_default();
 
         Self {
-            next_activity_id: value.next_activity_id.unwrap_or_else(|| {
-                value
-                    .activities
-                    .as_ref()
-                    .map(|activities| activities.iter().map(|x| x.id.0).max().unwrap_or(0) + 1)
-                    .unwrap_or(0)
-            }),
-            activities: value.activities.unwrap_or_default(),
-            date: value.date.unwrap_or_else(|| Utc::now().date_naive()),
+            next_activity_id: value
+                .next_activity_id
+                .unwrap_or_else(|| activities.iter().map(|x| x.id.0).max().unwrap_or(0) + 1),
+            activities,
+            date: value.date.unwrap_or_else(|| Local::now().date_naive()),
             current: value.current,
             buckets,
+            log: value.log.unwrap_or_default(),
+            history: value.history.unwrap_or_default(),
+            pomodoro: value.pomodoro,
+            recommendation_strategy: value.recommendation_strategy.unwrap_or_default(),
+            dirty: false,
+            read_only: false,
+            storage: None,
+            day_start: NaiveTime::MIN,
+            pomodoro_settings: PomodoroSettings::default(),
+            undo_stack: vec![],
+            redo_stack: vec![],
+            recording_undo: false,
         }
     }
 }
 impl State {
-    pub fn refresh(self) -> Self {
-        Self {
-            date: Utc::now().date_naive(),
+    /// A state with nothing in it, which is never saved
+    #[cfg(test)]
+    pub(crate) fn empty() -> Self {
+        Self::from(StateBuilder {
+            schema_version: migrations::CURRENT_SCHEMA_VERSION,
+            date: None,
+            activities: None,
+            log: None,
+            history: None,
+            next_activity_id: None,
+            current: None,
+            buckets: None,
+            pomodoro: None,
+            recommendation_strategy: None,
+        })
+    }
+
+    /// Archives the current day into the history,
+    /// and starts a new day with no time logged
+    pub fn refresh(&mut self) {
+        let today = self.today();
+        if self.date > today {
+            // the day starts later than it did when the date was stored, or the clock went back,
+            // so today is still the stored day, and archiving it would archive it twice
+            self.date = today;
+            self.update_today_targets();
+            self.dirty = true;
+            return;
+        }
+        if self.pomodoro_break().is_some() {
+            self.end_break(None);
+        }
+        self.split_current_session(today);
+        // the days in between, when timetrack wasn't opened, are archived with nothing logged
+        loop {
+            let day_record = self.day_record();
+            self.history.push(day_record);
+            self.carry_over_balances();
+            self.date = (self.date + TimeDelta::days(1)).min(today);
+            self.log.clear();
+            self.update_today_targets();
+            if self.date == today {
+                break;
+            }
+        }
+        // undoing would bring back the previous day's log
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.dirty = true;
+    }
+
+    /// Logs the part of the ongoing session which falls before the end of the stored day,
+    /// so that it is archived with that day, and credits the rest of the session to `today`
+    fn split_current_session(&mut self, today: NaiveDate) {
+        let Some(current) = &mut self.current else {
+            return;
+        };
+        let day_end = day::start_of(self.date + TimeDelta::days(1), self.day_start);
+        let credited_start = current.credited_start();
+        if credited_start < day_end {
+            self.log
+                .push(LogEntry::Session(current.session(day_end, false, true)));
+        }
+        current.credited_from = Some(credited_start.max(day::start_of(today, self.day_start)));
+    }
+
+    /// The day it is now, which `date` should be moved on to when it is out of date
+    pub fn today(&self) -> NaiveDate {
+        day::today(self.day_start)
+    }
+
+    pub(crate) fn set_day_start(&mut self, day_start: NaiveTime) {
+        self.day_start = day_start;
+    }
+
+    pub(crate) fn set_date(&mut self, date: NaiveDate) {
+        self.date = date;
+    }
+
+    pub(crate) fn set_pomodoro_settings(&mut self, pomodoro_settings: PomodoroSettings) {
+        self.pomodoro_settings = pomodoro_settings;
+    }
+
+    /// The stored day as it would be archived, which leaves out any ongoing session
+    pub fn day_record(&self) -> DayRecord {
+        DayRecord {
+            date: self.date,
             activities: self
                 .activities
-                .clone()
-                .into_iter()
-                .map(|x| x.with_acheived_reset())
+                .iter()
+                .map(|activity| ArchivedActivity {
+                    id: activity.id,
+                    name: activity.name.clone(),
+                    target_minutes: activity.today_target_minutes(),
+                    acheived_minutes: self.acheived_minutes(activity.id),
+                })
                 .collect(),
-            next_activity_id: self.next_activity_id,
-            current: self.current,
-            buckets: self.buckets.clone(),
+            log: self.log.clone(),
         }
     }
 
+    /// Every archived day, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &DayRecord> {
+        self.history.iter()
+    }
+
     pub fn add_activity(&mut self, name: String, target_minutes: usize) -> ActivityId {
-        let activity = Activity {
-            target_minutes,
-            acheived_minutes: 0,
-            name,
-            id: self.new_activity_id(),
-        };
-        let id = activity.id;
-        self.activities.push(activity);
-        id
+        self.undoable("add activity", |state| {
+            let activity = Activity::new(state.new_activity_id(), name, target_minutes);
+            let id = activity.id;
+            state.activities.push(activity);
+            state.dirty = true;
+            id
+        })
     }
 
     fn new_activity_id(&mut self) -> ActivityId {
@@ -127,24 +241,27 @@ impl State {
     }
 
     pub fn delete(&mut self, id: ActivityId) -> Result<(), DeletionError> {
-        if let Some(index) = self.get_index_by_id(id) {
-            if self
-                .current
-                .is_some_and(|current_action| id == current_action.activity_id)
-            {
-                if let Err(EndActivityError::PomoOngoing) = self.end_activity(false) {
+        self.undoable("delete activity", |state| {
+            if let Some(index) = state.get_index_by_id(id) {
+                if state
+                    .current
+                    .as_ref()
+                    .is_some_and(|current_action| id == current_action.activity_id)
+                    && let Err(EndActivityError::PomoOngoing) = state.end_activity(false)
+                {
                     return Err(DeletionError::PomoOngoing);
                 }
+                state.activities.remove(index);
+                state.dirty = true;
+                Ok(())
+            } else {
+                Err(DeletionError::InvalidId)
             }
-            self.activities.remove(index);
-            Ok(())
-        } else {
-            Err(DeletionError::InvalidId)
-        }
+        })
     }
 
     pub fn current_id(&self) -> Option<ActivityId> {
-        self.current.map(|x| x.activity_id)
+        self.current.as_ref().map(|x| x.activity_id)
     }
 
     pub fn start_activity(&mut self, id: ActivityId) -> Result<(), StartActivityError> {
@@ -156,59 +273,368 @@ impl State {
         id: ActivityId,
         pomo_minutes: Option<usize>,
     ) -> Result<(), StartActivityError> {
-        if self.current.is_some() {
-            Err(StartActivityError::AlreadyOngoing)
-        } else if self.get_index_by_id(id).is_some() {
-            self.current = Some(CurrentActionInfo::new(id, Utc::now(), pomo_minutes));
+        self.undoable("start activity", |state| {
+            if state.current.is_some() {
+                Err(StartActivityError::AlreadyOngoing)
+            } else if state.get_index_by_id(id).is_some() {
+                // the break so far is logged before its cycle is replaced
+                state.end_break(None);
+                state.current = Some(CurrentActionInfo::new(id, Utc::now(), pomo_minutes));
+                // a new pomodoro starts a new cycle, and any other session ends the old one
+                state.pomodoro = pomo_minutes.map(PomodoroCycle::new);
+                state.dirty = true;
+                Ok(())
+            } else {
+                Err(StartActivityError::InvalidId)
+            }
+        })
+    }
+
+    /// Starts a pomodoro on `chosen` instead of the recommended activity, and records the skip
+    pub fn skip_recommendation(
+        &mut self,
+        skipped: ActivityId,
+        chosen: ActivityId,
+        pomo_minutes: usize,
+    ) -> Result<(), StartActivityError> {
+        self.undoable("skip recommendation", |state| {
+            state.start_activity_pomo(chosen, Some(pomo_minutes))?;
+            state.log.push(LogEntry::Skip(Skip {
+                activity_id: skipped,
+                chosen,
+                time: Utc::now(),
+            }));
             Ok(())
-        } else {
-            Err(StartActivityError::InvalidId)
-        }
+        })
     }
 
     pub fn end_activity(&mut self, override_pomo: bool) -> Result<(), EndActivityError> {
-        if let Some(current) = self.current {
-            if !override_pomo && current.pomo_minutes.is_some() {
-                return Err(EndActivityError::PomoOngoing);
+        self.undoable("end activity", |state| {
+            if let Some(current) = &state.current {
+                if !override_pomo && current.pomo_minutes.is_some() {
+                    return Err(EndActivityError::PomoOngoing);
+                }
+                let end_time = Utc::now();
+                let overridden = current.timer_minutes().is_some_and(|timer_minutes| {
+                    current.active_time(end_time).num_minutes() < timer_minutes as i64
+                });
+                state.log.push(LogEntry::Session(
+                    current.session(end_time, overridden, false),
+                ));
+                state.current = None;
+                state.pomodoro = None;
+                state.dirty = true;
+                Ok(())
+            } else {
+                Err(EndActivityError::NoCurrentActivity)
             }
-            let now = Utc::now();
-            let delta = now - current.start_time;
-            if let Some(index) = self.get_index_by_id(current.activity_id) {
-                // this should always be the case
-                self.activities[index].acheived_minutes += delta.num_minutes().max(0) as usize;
+        })
+    }
+
+    /// Stops counting time towards the ongoing session until it is resumed
+    pub fn pause(&mut self) -> Result<(), PauseError> {
+        self.undoable("pause session", |state| {
+            let current = state
+                .current
+                .as_mut()
+                .ok_or(PauseError::NoCurrentActivity)?;
+            if current.paused_at.is_some() {
+                return Err(PauseError::AlreadyPaused);
             }
-            self.current = None;
+            current.paused_at = Some(Utc::now());
+            state.dirty = true;
             Ok(())
-        } else {
-            Err(EndActivityError::NoCurrentActivity)
-        }
+        })
     }
 
-    pub fn add_time(&mut self, id: ActivityId, minutes: usize) -> Result<(), ()> {
-        if let Some(index) = self.get_index_by_id(id) {
-            self.activities[index].acheived_minutes += minutes;
+    /// Starts counting time towards the paused session again, leaving the pause out of it
+    pub fn resume(&mut self) -> Result<(), PauseError> {
+        self.undoable("resume session", |state| {
+            let current = state
+                .current
+                .as_mut()
+                .ok_or(PauseError::NoCurrentActivity)?;
+            let paused_at = current.paused_at.take().ok_or(PauseError::NotPaused)?;
+            let now = Utc::now();
+            current.excluded.push(Span {
+                start: paused_at,
+                end: now,
+            });
+            // the pause may have been long, but that doesn't make it stale,
+            // and a running TUI sees the session again on its next tick
+            current.last_seen = None;
+            state.dirty = true;
             Ok(())
-        } else {
-            Err(())
-        }
+        })
     }
 
-    pub fn overwrite_time(&mut self, id: ActivityId, minutes: usize) -> Result<(), ()> {
-        if let Some(index) = self.get_index_by_id(id) {
-            self.activities[index].acheived_minutes = minutes;
+    pub fn is_paused(&self) -> bool {
+        self.current
+            .as_ref()
+            .is_some_and(|current| current.paused_at.is_some())
+    }
+
+    /// Adds `minutes` to the timer of the ongoing pomodoro, so that it carries on as the same session
+    pub fn extend_pomodoro(&mut self, minutes: usize) -> Result<(), ()> {
+        self.undoable("extend pomodoro", |state| {
+            let current = state
+                .current
+                .as_mut()
+                .filter(|current| current.pomo_minutes.is_some())
+                .ok_or(())?;
+            current.extended_minutes += minutes;
+            state.dirty = true;
             Ok(())
-        } else {
-            Err(())
+        })
+    }
+
+    /// When the timer of the ongoing pomodoro ran out, if it has
+    pub fn pomodoro_time_up(&self) -> Option<DateTime<Utc>> {
+        self.current
+            .as_ref()
+            .and_then(|current| current.timer_end(Utc::now()))
+    }
+
+    /// Ends a pomodoro work block whose timer is up at the time it ran out, and starts the break after it.
+    /// Returns the break, or `None` if the work block wasn't part of a cycle.
+    pub fn finish_work_block(&mut self) -> Option<PomodoroBreak> {
+        self.undoable("finish pomodoro", |state| {
+            let current = state.current.take()?;
+            let now = Utc::now();
+            let end_time = current.timer_end(now).unwrap_or(now);
+            state
+                .log
+                .push(LogEntry::Session(current.session(end_time, false, false)));
+            state.dirty = true;
+            let settings = state.pomodoro_settings;
+            let cycle = state.pomodoro.as_mut()?;
+            cycle.completed += 1;
+            let long = cycle.completed >= settings.long_break_every;
+            if long {
+                cycle.completed = 0;
+            }
+            let pomodoro_break = PomodoroBreak {
+                activity_id: current.activity_id,
+                start_time: end_time,
+                minutes: if long {
+                    settings.long_break_minutes
+                } else {
+                    settings.short_break_minutes
+                },
+                long,
+            };
+            cycle.on_break = Some(pomodoro_break);
+            Some(pomodoro_break)
+        })
+    }
+
+    /// Ends the ongoing break, and starts the next work block of the cycle on `next`.
+    /// If `next` is `None`, the cycle ends instead.
+    pub fn end_break(&mut self, next: Option<ActivityId>) {
+        self.undoable("end break", |state| {
+            let next = next.filter(|id| state.get_index_by_id(*id).is_some());
+            let Some(cycle) = &mut state.pomodoro else {
+                return;
+            };
+            let Some(pomodoro_break) = cycle.on_break.take() else {
+                return;
+            };
+            let now = Utc::now();
+            state.log.push(LogEntry::Break(Break {
+                activity_id: pomodoro_break.activity_id,
+                start_time: pomodoro_break.start_time,
+                end_time: now.min(pomodoro_break.end_time()),
+                long: pomodoro_break.long,
+            }));
+            match next {
+                Some(id) => {
+                    state.current = Some(CurrentActionInfo::new(id, now, Some(cycle.work_minutes)))
+                }
+                None => state.pomodoro = None,
+            }
+            state.dirty = true;
+        })
+    }
+
+    pub fn pomodoro_cycle(&self) -> Option<&PomodoroCycle> {
+        self.pomodoro.as_ref()
+    }
+
+    pub fn pomodoro_break(&self) -> Option<&PomodoroBreak> {
+        self.pomodoro_cycle()
+            .and_then(|cycle| cycle.on_break.as_ref())
+    }
+
+    pub(crate) fn pomodoro_settings(&self) -> PomodoroSettings {
+        self.pomodoro_settings
+    }
+
+    /// Looks for time in the ongoing session which probably wasn't spent on it:
+    /// a gap since the TUI was last seen running, which points to a suspend or a long absence,
+    /// or a session which has been running for implausibly long
+    pub fn stale_time(&self, now: DateTime<Utc>) -> Option<StaleTime> {
+        let current = self.current.as_ref()?;
+        // none of the time since the session was paused counts, so none of it can be stale
+        if current.paused_at.is_some() {
+            return None;
+        }
+        if let Some(last_seen) = current.last_seen
+            && now - last_seen > STALE_GAP
+        {
+            return Some(StaleTime {
+                activity_id: current.activity_id,
+                reason: StaleReason::Gap,
+                from: last_seen,
+                to: now,
+            });
         }
+        let reviewed_from = current
+            .reviewed_until
+            .map_or(current.start_time, |reviewed_until| {
+                reviewed_until.max(current.start_time)
+            });
+        (now - reviewed_from > MAX_SESSION).then_some(StaleTime {
+            activity_id: current.activity_id,
+            reason: StaleReason::LongSession,
+            from: reviewed_from + MAX_SESSION,
+            to: now,
+        })
+    }
+
+    /// Records that the TUI was running at `now`.
+    /// This only counts as a change every `LAST_SEEN_INTERVAL`, so that it doesn't cause a save on every tick.
+    pub fn mark_seen(&mut self, now: DateTime<Utc>) {
+        if let Some(current) = &mut self.current {
+            if current
+                .last_seen
+                .is_none_or(|last_seen| now - last_seen >= LAST_SEEN_INTERVAL)
+            {
+                self.dirty = true;
+            }
+            current.last_seen = Some(now);
+        }
+    }
+
+    /// Records that the TUI is closing, so that the time until it is opened again isn't taken as a gap.
+    /// Nobody is watching the session in the meantime, so there is no way to tell whether the computer slept.
+    pub fn forget_seen(&mut self) {
+        if let Some(current) = &mut self.current
+            && current.last_seen.take().is_some()
+        {
+            self.dirty = true;
+        }
+    }
+
+    /// Applies the user's decision about stale time found by `stale_time`, and records it in the log
+    pub fn resolve_stale_time(&mut self, stale: StaleTime, resolution: StaleResolution) {
+        self.undoable("resolve stale time", |state| {
+            let Some(current) = &mut state.current else {
+                return;
+            };
+            if current.activity_id != stale.activity_id {
+                return;
+            }
+            let now = Utc::now();
+            match resolution {
+                StaleResolution::Keep => (),
+                StaleResolution::Trim => {
+                    let end_time = stale.from.max(current.credited_start());
+                    let overridden = current.timer_minutes().is_some_and(|timer_minutes| {
+                        current.active_time(end_time).num_minutes() < timer_minutes as i64
+                    });
+                    state.log.push(LogEntry::Session(
+                        current.session(end_time, overridden, false),
+                    ));
+                }
+                StaleResolution::Discard => current.excluded.push(Span {
+                    start: stale.from,
+                    end: stale.to,
+                }),
+            }
+            if let Some(current) = &mut state.current {
+                current.last_seen = Some(now);
+                current.reviewed_until = Some(stale.to);
+            }
+            if resolution == StaleResolution::Trim {
+                state.current = None;
+            }
+            state.log.push(LogEntry::StaleTime(StaleTimeDecision {
+                activity_id: stale.activity_id,
+                time: now,
+                reason: stale.reason,
+                from: stale.from,
+                to: stale.to,
+                resolution,
+            }));
+            state.dirty = true;
+        })
+    }
+
+    pub fn add_time(&mut self, id: ActivityId, minutes: usize) -> Result<(), ()> {
+        self.undoable("register time", |state| {
+            if state.get_index_by_id(id).is_some() {
+                state.log.push(LogEntry::Correction(Correction {
+                    activity_id: id,
+                    time: Utc::now(),
+                    minutes: minutes as i64,
+                }));
+                state.dirty = true;
+                Ok(())
+            } else {
+                Err(())
+            }
+        })
+    }
+
+    /// Adds a correction to the log so that the acheived time
+    /// for the activity becomes `minutes`
+    pub fn overwrite_time(&mut self, id: ActivityId, minutes: usize) -> Result<(), ()> {
+        self.undoable("overwrite time", |state| {
+            if state.get_index_by_id(id).is_some() {
+                state.log.push(LogEntry::Correction(Correction {
+                    activity_id: id,
+                    time: Utc::now(),
+                    minutes: minutes as i64 - state.acheived_minutes(id) as i64,
+                }));
+                state.dirty = true;
+                Ok(())
+            } else {
+                Err(())
+            }
+        })
+    }
+
+    /// The time logged for an activity today, excluding any ongoing session
+    pub fn acheived_minutes(&self, id: ActivityId) -> usize {
+        self.log
+            .iter()
+            .filter(|entry| entry.activity_id() == id)
+            .map(LogEntry::minutes)
+            .sum::<i64>()
+            .max(0) as usize
+    }
+
+    /// The time spent on an activity today, including any ongoing session
+    pub fn minutes_today(&self, id: ActivityId) -> usize {
+        self.acheived_minutes(id)
+            + if self.current_id() == Some(id) {
+                self.current_task_minutes().unwrap_or(0)
+            } else {
+                0
+            }
     }
 
     pub fn date(&self) -> NaiveDate {
         self.date
     }
 
+    /// The time the ongoing session has added to today, which excludes any part of it
+    /// that was credited to a previous day
     pub fn current_task_minutes(&self) -> Option<usize> {
-        self.current.map(|current_activity| {
-            (Utc::now() - current_activity.start_time)
+        self.current.as_ref().map(|current_activity| {
+            let now = Utc::now();
+            let credited_start = current_activity.credited_start();
+            (now - credited_start - current_activity.excluded_time(credited_start, now))
                 .num_minutes()
                 .max(0) as usize
         })
@@ -218,16 +644,18 @@ impl State {
         self.activities.iter().find(|activity| activity.id == id)
     }
 
+    /// How long the ongoing session has been running, including any part of it before today
     pub fn current_session_duration(&self) -> Option<TimeDelta> {
         self.current
-            .map(|current_activity| Utc::now() - current_activity.start_time)
+            .as_ref()
+            .map(|current_activity| current_activity.active_time(Utc::now()))
     }
 
     pub fn current_activity(&self) -> Option<&Activity> {
         self.current_id().map(|id| self.activity_by_id(id).unwrap())
     }
 
-    pub fn format_activity(&self, activity: &Activity, max_name_length: Option<usize>) -> Line {
+    pub fn format_activity(&self, activity: &Activity, max_name_length: Option<usize>) -> Line<'_> {
         let pad = |s: &str| {
             if let Some(max_name_length) = max_name_length {
                 let current_length = s.chars().count();
@@ -237,16 +665,22 @@ impl State {
             }
         };
         let ongoing = self.current_id().is_some_and(|x| x == activity.id());
-        let acheived = activity.acheived_minutes()
+        let acheived = self.acheived_minutes(activity.id())
             + if ongoing {
                 self.current_task_minutes().unwrap_or(0)
             } else {
                 0
             };
-        let target = activity.target_minutes();
+        let target = activity.today_target_minutes();
         let remaining = target.saturating_sub(acheived);
+        let paused = ongoing && self.is_paused();
+        let day_off = !ongoing && target == 0 && acheived == 0;
         let status = {
-            let status = if ongoing {
+            let status = if paused {
+                "PAUSED  "
+            } else if day_off {
+                "DAY OFF "
+            } else if ongoing {
                 if acheived < target {
                     "ONGOING "
                 } else {
@@ -258,7 +692,11 @@ impl State {
                 "COMPLETE"
             };
 
-            if ongoing {
+            if paused {
+                status.yellow()
+            } else if day_off {
+                status.dark_gray()
+            } else if ongoing {
                 if acheived < target {
                     status.blue()
                 } else {
@@ -278,43 +716,241 @@ impl State {
             remaining.to_string().into(),
             " / ".into(),
             target.to_string().into(),
+            // owed time is carried into the target, and time worked ahead is taken off it
+            match activity.carry_over() {
+                Some(_) if activity.balance() > 0 => format!(" {:+}", activity.balance()).red(),
+                Some(_) if activity.balance() < 0 => format!(" {:+}", activity.balance()).green(),
+                Some(_) => " +0".dark_gray(),
+                None => "".into(),
+            },
+            if activity.priority() > 0 {
+                format!(" P{}", activity.priority()).magenta()
+            } else {
+                "".into()
+            },
         ])
     }
 
-    fn save_state(&self) -> Result<(), Box<dyn std::error::Error>> {
-        std::fs::write(
-            stored_state_file_path()?,
-            serde_json::to_string(&StateBuilder {
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub(crate) fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    pub(crate) fn set_storage(&mut self, storage: Rc<dyn Storage>) {
+        self.storage = Some(storage);
+    }
+
+    /// Writes the state to disk if there are unsaved changes
+    pub fn save(&mut self) -> color_eyre::Result<()> {
+        if self.dirty && !self.read_only {
+            self.save_state()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn save_state(&self) -> color_eyre::Result<()> {
+        if let Some(storage) = &self.storage {
+            storage.save(&StateBuilder {
+                schema_version: migrations::CURRENT_SCHEMA_VERSION,
                 date: Some(self.date),
                 activities: Some(self.activities.clone()),
+                log: Some(self.log.clone()),
+                history: Some(self.history.clone()),
                 next_activity_id: Some(self.next_activity_id),
-                current: self.current,
-                todo: None,
-                buckets_v2: Some(self.buckets.clone()),
-                todo_v2: None,
-                buckets: None,
-            })
-            .expect("should be able to convert to string"),
-        )?;
+                current: self.current.clone(),
+                buckets: Some(self.buckets.clone()),
+                pomodoro: self.pomodoro.clone(),
+                recommendation_strategy: Some(self.recommendation_strategy),
+            })?;
+        }
         Ok(())
     }
 
-    pub(crate) fn get_by_raw_id_mut(&mut self, id: usize) -> Option<&mut Activity> {
-        self.activities
-            .iter_mut()
-            .find(|activity| activity.id == ActivityId(id))
+    /// Changes the target of a daily activity. Activities with another schedule keep it,
+    /// and only the stored day's target changes, until the next day is worked out from the schedule.
+    pub fn set_target_minutes(&mut self, id: ActivityId, target_minutes: usize) -> Result<(), ()> {
+        self.undoable("change target", |state| {
+            let activity = state
+                .activities
+                .iter_mut()
+                .find(|activity| activity.id == id)
+                .ok_or(())?;
+            if activity.schedule.is_daily() {
+                activity.set_target_minutes(target_minutes);
+            } else {
+                activity.today_target_minutes = Some(target_minutes);
+            }
+            state.dirty = true;
+            Ok(())
+        })
+    }
+
+    /// Sets the target for every day, which makes the activity daily if it had another schedule
+    pub fn set_daily_target(&mut self, id: ActivityId, target_minutes: usize) -> Result<(), ()> {
+        self.undoable("change schedule", |state| {
+            let activity = state
+                .activities
+                .iter_mut()
+                .find(|activity| activity.id == id)
+                .ok_or(())?;
+            activity.set_target_minutes(target_minutes);
+            activity.schedule = Schedule::Daily;
+            activity.today_target_minutes = None;
+            state.dirty = true;
+            Ok(())
+        })
+    }
+
+    pub fn set_schedule(&mut self, id: ActivityId, schedule: Schedule) -> Result<(), ()> {
+        self.undoable("change schedule", |state| {
+            let activity = state
+                .activities
+                .iter_mut()
+                .find(|activity| activity.id == id)
+                .ok_or(())?;
+            activity.schedule = schedule;
+            state.update_today_targets();
+            state.dirty = true;
+            Ok(())
+        })
+    }
+
+    /// Carries the time an activity falls short of its target into the next day, or turns that off
+    /// and forgets the balance when `carry_over` is `None`
+    pub fn set_carry_over(
+        &mut self,
+        id: ActivityId,
+        carry_over: Option<CarryOver>,
+    ) -> Result<(), ()> {
+        self.undoable("change carry over", |state| {
+            let activity = state
+                .activities
+                .iter_mut()
+                .find(|activity| activity.id == id)
+                .ok_or(())?;
+            activity.carry_over = carry_over;
+            if carry_over.is_none() {
+                activity.balance = 0;
+            }
+            state.dirty = true;
+            Ok(())
+        })
     }
 
-    pub(crate) fn get_by_id_mut(&mut self, id: ActivityId) -> Option<&mut Activity> {
-        self.get_by_raw_id_mut(id.0)
+    /// Moves what was owed or worked ahead on the stored day into each activity's balance
+    fn carry_over_balances(&mut self) {
+        let balances: Vec<i64> = self
+            .activities
+            .iter()
+            .map(|activity| match activity.carry_over {
+                Some(carry_over) => carry_over.carry(
+                    activity.balance,
+                    activity.scheduled_minutes(),
+                    self.acheived_minutes(activity.id),
+                ),
+                None => 0,
+            })
+            .collect();
+        for (activity, balance) in self.activities.iter_mut().zip(balances) {
+            activity.balance = balance;
+        }
+    }
+
+    /// Works out each activity's target for the stored day from its schedule
+    fn update_today_targets(&mut self) {
+        let week_start = schedule::week_start(self.date);
+        let targets: Vec<Option<usize>> = self
+            .activities
+            .iter()
+            .map(|activity| {
+                if activity.schedule.is_daily() {
+                    return None;
+                }
+                let done_this_week = self
+                    .history
+                    .iter()
+                    .filter(|day| week_start <= day.date && day.date < self.date)
+                    .flat_map(|day| &day.activities)
+                    .filter(|archived| archived.id == activity.id)
+                    .map(|archived| archived.acheived_minutes)
+                    .sum();
+                Some(activity.schedule.target_on(
+                    self.date,
+                    activity.target_minutes,
+                    done_this_week,
+                ))
+            })
+            .collect();
+        for (activity, target) in self.activities.iter_mut().zip(targets) {
+            activity.today_target_minutes = target;
+        }
+    }
+
+    /// Higher priorities are recommended first by the priority strategy
+    pub fn set_priority(&mut self, id: ActivityId, priority: usize) -> Result<(), ()> {
+        self.undoable("change priority", |state| {
+            let activity = state
+                .activities
+                .iter_mut()
+                .find(|activity| activity.id == id)
+                .ok_or(())?;
+            activity.set_priority(priority);
+            state.dirty = true;
+            Ok(())
+        })
+    }
+
+    pub fn recommendation_strategy(&self) -> StrategyKind {
+        self.recommendation_strategy
+    }
+
+    pub fn set_recommendation_strategy(&mut self, strategy: StrategyKind) {
+        self.undoable("change strategy", |state| {
+            if state.recommendation_strategy != strategy {
+                state.recommendation_strategy = strategy;
+                state.dirty = true;
+            }
+        })
+    }
+
+    /// When the last session of an activity logged today ended
+    pub fn last_worked_time(&self, id: ActivityId) -> Option<DateTime<Utc>> {
+        self.log.iter().rev().find_map(|entry| match entry {
+            LogEntry::Session(session) if session.activity_id == id => Some(session.end_time),
+            _ => None,
+        })
+    }
+
+    /// The activity of the last session logged today
+    pub fn last_worked_activity(&self) -> Option<ActivityId> {
+        self.log.iter().rev().find_map(|entry| match entry {
+            LogEntry::Session(session) => Some(session.activity_id),
+            _ => None,
+        })
+    }
+
+    /// A number which only changes when the day or the log changes
+    pub fn recommendation_seed(&self) -> u64 {
+        self.date.num_days_from_ce() as u64 * 10_000 + self.log.len() as u64
     }
 
     pub(crate) fn activities_count(&self) -> usize {
         self.activities.len()
     }
 
+    /// The length of the ongoing pomodoro's timer, including any extensions
     pub(crate) fn pomo_minutes(&self) -> Option<usize> {
-        self.current.as_ref().and_then(|x| x.pomo_minutes)
+        self.current
+            .as_ref()
+            .and_then(CurrentActionInfo::timer_minutes)
     }
 }
 impl State {
@@ -322,7 +958,8 @@ impl State {
         self.buckets.iter()
     }
 
-    pub(crate) fn get_buckets_mut(&mut self) -> impl Iterator<Item = &mut Bucket> {
+    fn get_buckets_mut(&mut self) -> impl Iterator<Item = &mut Bucket> {
+        self.dirty = true;
         self.buckets.iter_mut()
     }
 
@@ -330,27 +967,33 @@ impl State {
     /// and false if its todos were combined
     /// with an already existing bucket
     pub(crate) fn create_bucket(&mut self, bucket: Bucket) -> bool {
-        if let Some(stored_bucket) = self.get_buckets_mut().find(|x| *x == &bucket) {
-            stored_bucket
-                .todos_mut()
-                .extend(bucket.todos().map(TodoItem::clone));
-            false
-        } else {
-            self.buckets.push(bucket);
-            true
-        }
+        self.undoable("create bucket", |state| {
+            if let Some(stored_bucket) = state.get_buckets_mut().find(|x| *x == &bucket) {
+                stored_bucket
+                    .todos_mut()
+                    .extend(bucket.todos().map(TodoItem::clone));
+                false
+            } else {
+                state.buckets.push(bucket);
+                state.dirty = true;
+                true
+            }
+        })
     }
 
     pub(crate) fn delete_bucket(&mut self, index: usize) -> bool {
-        if self.buckets.len() > index
-            && self.buckets[index].name() != DEFAULT_BUCKET_NAME
-            && self.buckets[index].todos().count() == 0
-        {
-            self.buckets.remove(index);
-            true
-        } else {
-            false
-        }
+        self.undoable("delete bucket", |state| {
+            if state.buckets.len() > index
+                && state.buckets[index].name() != DEFAULT_BUCKET_NAME
+                && state.buckets[index].todos().count() == 0
+            {
+                state.buckets.remove(index);
+                state.dirty = true;
+                true
+            } else {
+                false
+            }
+        })
     }
 
     pub(crate) fn change_bucket_index(
@@ -358,20 +1001,207 @@ impl State {
         original_index: usize,
         new_index: usize,
     ) -> Result<(), BucketSwapError> {
-        if original_index >= self.buckets.len() {
-            Err(BucketSwapError::InvalidSelection)
-        } else if new_index >= self.buckets.len() {
-            Err(BucketSwapError::InvalidTargetIndex)
-        } else {
-            let bucket = self.buckets.remove(original_index);
-            self.buckets.insert(new_index, bucket);
+        self.undoable("move bucket", |state| {
+            if original_index >= state.buckets.len() {
+                Err(BucketSwapError::InvalidSelection)
+            } else if new_index >= state.buckets.len() {
+                Err(BucketSwapError::InvalidTargetIndex)
+            } else {
+                let bucket = state.buckets.remove(original_index);
+                state.buckets.insert(new_index, bucket);
+                state.dirty = true;
+                Ok(())
+            }
+        })
+    }
+}
+impl State {
+    pub(crate) fn add_todo(&mut self, bucket_index: usize, todo: TodoItem) -> Result<(), ()> {
+        self.undoable("add todo", |state| {
+            state
+                .buckets
+                .get_mut(bucket_index)
+                .ok_or(())?
+                .push_todo(todo);
+            state.dirty = true;
+            Ok(())
+        })
+    }
+
+    pub(crate) fn delete_todo(
+        &mut self,
+        bucket_index: usize,
+        todo_index: usize,
+    ) -> Result<TodoItem, TodoDeletionError> {
+        self.undoable("delete todo", |state| {
+            state.remove_todo(bucket_index, todo_index)
+        })
+    }
+
+    fn remove_todo(
+        &mut self,
+        bucket_index: usize,
+        todo_index: usize,
+    ) -> Result<TodoItem, TodoDeletionError> {
+        let todos = self
+            .buckets
+            .get_mut(bucket_index)
+            .ok_or(TodoDeletionError::InvalidIdOrBucket)?
+            .todos_mut();
+        if todo_index >= todos.len() {
+            return Err(TodoDeletionError::InvalidId);
+        }
+        self.dirty = true;
+        Ok(todos.remove(todo_index))
+    }
+
+    pub(crate) fn swap_todos(
+        &mut self,
+        bucket_index: usize,
+        first: usize,
+        second: usize,
+    ) -> Result<(), TodoSwapError> {
+        self.undoable("move todo", |state| {
+            let todos = state
+                .buckets
+                .get_mut(bucket_index)
+                .ok_or(TodoSwapError::InvalidBucket)?
+                .todos_mut();
+            if first >= todos.len() {
+                Err(TodoSwapError::FirstInvalid)
+            } else if second >= todos.len() {
+                Err(TodoSwapError::SecondInvalid)
+            } else if first == second {
+                Err(TodoSwapError::EqualIds)
+            } else {
+                todos.swap(first, second);
+                state.dirty = true;
+                Ok(())
+            }
+        })
+    }
+
+    /// Moves a todo to the end of another bucket
+    pub(crate) fn move_todo(
+        &mut self,
+        bucket_index: usize,
+        todo_index: usize,
+        target_bucket_index: usize,
+    ) -> Result<(), TodoDeletionError> {
+        self.undoable("move todo to bucket", |state| {
+            if target_bucket_index >= state.buckets.len() {
+                return Err(TodoDeletionError::InvalidIdOrBucket);
+            }
+            let todo = state.remove_todo(bucket_index, todo_index)?;
+            state.buckets[target_bucket_index].push_todo(todo);
             Ok(())
+        })
+    }
+}
+impl State {
+    /// Runs `change`, and if it changed anything, lets it be undone.
+    /// Changes made by other undoable operations inside `change` are undone along with it.
+    fn undoable<T>(&mut self, label: &'static str, change: impl FnOnce(&mut Self) -> T) -> T {
+        if self.recording_undo {
+            return change(self);
+        }
+        let snapshot = self.snapshot();
+        let was_dirty = self.dirty;
+        self.dirty = false;
+        self.recording_undo = true;
+        let result = change(self);
+        self.recording_undo = false;
+        if self.dirty {
+            self.undo_stack.push(UndoEntry { label, snapshot });
+            self.redo_stack.clear();
+        }
+        self.dirty |= was_dirty;
+        result
+    }
+
+    /// Runs `change`, which the passing of time made rather than the user, so that it can't be undone.
+    /// Undoing an older operation would also bring back what `change` replaced, so if it changed anything,
+    /// the undo and redo history is cleared.
+    pub fn automatic<T>(&mut self, change: impl FnOnce(&mut Self) -> T) -> T {
+        let was_dirty = self.dirty;
+        self.dirty = false;
+        self.recording_undo = true;
+        let result = change(self);
+        self.recording_undo = false;
+        if self.dirty {
+            self.undo_stack.clear();
+            self.redo_stack.clear();
+        }
+        self.dirty |= was_dirty;
+        result
+    }
+
+    /// Reverts the most recent undoable operation, returning its label
+    pub fn undo(&mut self) -> Option<&'static str> {
+        let entry = self.undo_stack.pop()?;
+        let redo = self.restore(entry);
+        let label = redo.label;
+        self.redo_stack.push(redo);
+        Some(label)
+    }
+
+    /// Reapplies the most recently undone operation, returning its label
+    pub fn redo(&mut self) -> Option<&'static str> {
+        let entry = self.redo_stack.pop()?;
+        let undo = self.restore(entry);
+        let label = undo.label;
+        self.undo_stack.push(undo);
+        Some(label)
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            activities: self.activities.clone(),
+            next_activity_id: self.next_activity_id,
+            current: self.current.clone(),
+            buckets: self.buckets.clone(),
+            log: self.log.clone(),
+            pomodoro: self.pomodoro.clone(),
+            recommendation_strategy: self.recommendation_strategy,
+        }
+    }
+
+    /// Puts the state back to `entry`, and returns an entry which would reverse that
+    fn restore(&mut self, entry: UndoEntry) -> UndoEntry {
+        let reverse = UndoEntry {
+            label: entry.label,
+            snapshot: self.snapshot(),
+        };
+        let Snapshot {
+            activities,
+            next_activity_id,
+            current,
+            buckets,
+            log,
+            pomodoro,
+            recommendation_strategy,
+        } = entry.snapshot;
+        self.activities = activities;
+        self.next_activity_id = next_activity_id;
+        self.current = current;
+        self.buckets = buckets;
+        self.log = log;
+        self.pomodoro = pomodoro;
+        self.recommendation_strategy = recommendation_strategy;
+        // the program has been running all along, so a restored session has no stale time
+        if let Some(current) = &mut self.current {
+            current.last_seen = Some(Utc::now());
         }
+        self.dirty = true;
+        reverse
     }
 }
 impl Drop for State {
     fn drop(&mut self) {
-        self.save_state().expect("should be able to save state");
+        // a last resort for changes the explicit saves missed, which can't fail loudly from here
+        if let Err(error) = self.save() {
+            eprintln!("Couldn't save the state: {error}");
+        }
     }
 }
 
@@ -395,11 +1225,49 @@ pub enum BucketSwapError {
     InvalidTargetIndex,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// The parts of the state which undo and redo put back
+#[derive(Debug, Clone)]
+struct Snapshot {
+    activities: Vec<Activity>,
+    next_activity_id: usize,
+    current: Option<CurrentActionInfo>,
+    buckets: Vec<Bucket>,
+    log: Vec<LogEntry>,
+    pomodoro: Option<PomodoroCycle>,
+    recommendation_strategy: StrategyKind,
+}
+
+#[derive(Debug, Clone)]
+struct UndoEntry {
+    label: &'static str,
+    /// The state from before the operation
+    snapshot: Snapshot,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CurrentActionInfo {
     activity_id: ActivityId,
     start_time: DateTime<Utc>,
     pomo_minutes: Option<usize>,
+    /// Where today's share of the session starts, if the session began on an earlier day
+    #[serde(default)]
+    credited_from: Option<DateTime<Utc>>,
+    /// The last time the TUI was seen running during this session,
+    /// which is `None` while the TUI isn't open
+    #[serde(default)]
+    last_seen: Option<DateTime<Utc>>,
+    /// The end of the last stale time the user made a decision about
+    #[serde(default)]
+    reviewed_until: Option<DateTime<Utc>>,
+    /// Time which the user chose not to count towards this session
+    #[serde(default)]
+    excluded: Vec<Span>,
+    /// When the session was paused, if it is paused now
+    #[serde(default)]
+    paused_at: Option<DateTime<Utc>>,
+    /// Minutes added to the pomodoro's timer after it ran out
+    #[serde(default)]
+    extended_minutes: usize,
 }
 impl CurrentActionInfo {
     fn new(
@@ -411,6 +1279,66 @@ impl CurrentActionInfo {
             activity_id,
             start_time,
             pomo_minutes,
+            credited_from: None,
+            last_seen: None,
+            reviewed_until: None,
+            excluded: vec![],
+            paused_at: None,
+            extended_minutes: 0,
+        }
+    }
+
+    fn timer_minutes(&self) -> Option<usize> {
+        self.pomo_minutes
+            .map(|pomo_minutes| pomo_minutes + self.extended_minutes)
+    }
+
+    /// When the pomodoro's timer ran out, if it has by `now`
+    fn timer_end(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let overtime = self.active_time(now) - TimeDelta::minutes(self.timer_minutes()? as i64);
+        (overtime >= TimeDelta::zero()).then(|| now - overtime)
+    }
+
+    fn credited_start(&self) -> DateTime<Utc> {
+        self.credited_from.unwrap_or(self.start_time)
+    }
+
+    /// The excluded spans, including the ongoing pause up to `until`
+    fn excluded_spans(&self, until: DateTime<Utc>) -> impl Iterator<Item = Span> + '_ {
+        self.excluded.iter().copied().chain(
+            self.paused_at
+                .filter(|paused_at| *paused_at < until)
+                .map(|paused_at| Span {
+                    start: paused_at,
+                    end: until,
+                }),
+        )
+    }
+
+    fn excluded_time(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> TimeDelta {
+        Span::total_overlap(self.excluded_spans(end), start, end)
+    }
+
+    /// How long the session has run for up to `end`, leaving out excluded time
+    fn active_time(&self, end: DateTime<Utc>) -> TimeDelta {
+        end - self.start_time - self.excluded_time(self.start_time, end)
+    }
+
+    /// The part of the session which hasn't been logged yet, up to `end_time`
+    fn session(&self, end_time: DateTime<Utc>, overridden: bool, split: bool) -> Session {
+        let start_time = self.credited_start();
+        Session {
+            activity_id: self.activity_id,
+            start_time,
+            end_time,
+            pomo_minutes: self.pomo_minutes,
+            extended_minutes: self.extended_minutes,
+            overridden,
+            split,
+            excluded: self
+                .excluded_spans(end_time)
+                .filter(|span| span.overlap(start_time, end_time) > TimeDelta::zero())
+                .collect(),
         }
     }
 }
@@ -428,6 +1356,13 @@ pub enum EndActivityError {
     NoCurrentActivity,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseError {
+    NoCurrentActivity,
+    AlreadyPaused,
+    NotPaused,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DeletionError {
     PomoOngoing,
@@ -437,11 +1372,47 @@ pub enum DeletionError {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Activity {
     target_minutes: usize,
-    acheived_minutes: usize,
     name: String,
     id: ActivityId,
+    /// Left out of the state file when it is 0, which is what activities without one default to
+    #[serde(default, skip_serializing_if = "is_zero")]
+    priority: usize,
+    /// Left out of the state file for daily activities, which is what activities without one are
+    #[serde(default, skip_serializing_if = "Schedule::is_daily")]
+    schedule: Schedule,
+    /// The target for the stored day, worked out from the schedule when the day is refreshed.
+    /// Daily activities have none, and use `target_minutes`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    today_target_minutes: Option<usize>,
+    /// Left out of the state file for activities which don't carry time over
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    carry_over: Option<CarryOver>,
+    /// The time owed from previous days, or worked ahead of them if it is negative
+    #[serde(default, skip_serializing_if = "is_zero_balance")]
+    balance: i64,
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn is_zero_balance(value: &i64) -> bool {
+    *value == 0
 }
 impl Activity {
+    pub(crate) fn new(id: ActivityId, name: String, target_minutes: usize) -> Self {
+        Self {
+            target_minutes,
+            name,
+            id,
+            priority: 0,
+            schedule: Schedule::Daily,
+            today_target_minutes: None,
+            carry_over: None,
+            balance: 0,
+        }
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -450,43 +1421,683 @@ impl Activity {
         self.id
     }
 
-    pub fn target_minutes(&self) -> usize {
+    /// The target on the days the daily schedule is used
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn target_minutes(&self) -> usize {
         self.target_minutes
     }
 
-    pub fn acheived_minutes(&self) -> usize {
-        self.acheived_minutes
+    /// The target for the stored day, which depends on the schedule and the balance.
+    /// Days off stay off, and the balance waits for the next day with a target.
+    pub fn today_target_minutes(&self) -> usize {
+        let scheduled = self.scheduled_minutes();
+        if scheduled == 0 {
+            0
+        } else {
+            (scheduled as i64 + self.balance).max(0) as usize
+        }
     }
 
-    fn with_acheived_reset(self) -> Self {
-        Self {
-            acheived_minutes: 0,
-            ..self
-        }
+    /// The target for the stored day according to the schedule alone
+    fn scheduled_minutes(&self) -> usize {
+        self.today_target_minutes.unwrap_or(self.target_minutes)
+    }
+
+    pub fn carry_over(&self) -> Option<CarryOver> {
+        self.carry_over
+    }
+
+    pub fn balance(&self) -> i64 {
+        self.balance
+    }
+
+    pub fn schedule(&self) -> Schedule {
+        self.schedule
     }
 
     pub(crate) fn set_target_minutes(&mut self, target_minutes: usize) {
         self.target_minutes = target_minutes;
     }
+
+    pub fn priority(&self) -> usize {
+        self.priority
+    }
+
+    pub(crate) fn set_priority(&mut self, priority: usize) {
+        self.priority = priority;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogEntry {
+    Session(Session),
+    /// Time added or removed by hand, via `State::add_time` or `State::overwrite_time`
+    Correction(Correction),
+    /// What the user decided to do about time in a session which probably wasn't spent on it.
+    /// The decision itself is already reflected in the session, so this credits no time.
+    StaleTime(StaleTimeDecision),
+    /// A break between pomodoros, which credits no time
+    Break(Break),
+    /// A recommended activity which was passed over for the next one, which credits no time
+    Skip(Skip),
+}
+impl LogEntry {
+    pub fn activity_id(&self) -> ActivityId {
+        match self {
+            LogEntry::Session(session) => session.activity_id,
+            LogEntry::Correction(correction) => correction.activity_id,
+            LogEntry::StaleTime(decision) => decision.activity_id,
+            LogEntry::Break(pomodoro_break) => pomodoro_break.activity_id,
+            LogEntry::Skip(skip) => skip.activity_id,
+        }
+    }
+
+    /// The number of minutes this entry credits to its activity
+    pub fn minutes(&self) -> i64 {
+        match self {
+            LogEntry::Session(session) => session.minutes() as i64,
+            LogEntry::Correction(correction) => correction.minutes,
+            LogEntry::StaleTime(_) | LogEntry::Break(_) | LogEntry::Skip(_) => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    pub activity_id: ActivityId,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// The planned length of the session, if it was a pomodoro
+    pub pomo_minutes: Option<usize>,
+    /// Minutes the pomodoro was extended by after its timer ran out
+    #[serde(default)]
+    pub extended_minutes: usize,
+    /// True if a pomodoro session was ended before its timer was up
+    pub overridden: bool,
+    /// True if the session was still running at the end of the day,
+    /// in which case the rest of it is logged on the following day
+    #[serde(default)]
+    pub split: bool,
+    /// Time within the session which doesn't count towards it
+    #[serde(default)]
+    pub excluded: Vec<Span>,
+}
+impl Session {
+    pub fn minutes(&self) -> usize {
+        let excluded = Span::total_overlap(
+            self.excluded.iter().copied(),
+            self.start_time,
+            self.end_time,
+        );
+        (self.end_time - self.start_time - excluded)
+            .num_minutes()
+            .max(0) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Break {
+    /// The activity which was worked on before the break
+    pub activity_id: ActivityId,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub long: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Skip {
+    /// The activity which was recommended, and skipped
+    pub activity_id: ActivityId,
+    /// The activity which was worked on instead
+    pub chosen: ActivityId,
+    pub time: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+impl Span {
+    /// How much of this span falls between `start` and `end`
+    pub fn overlap(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> TimeDelta {
+        (self.end.min(end) - self.start.max(start)).max(TimeDelta::zero())
+    }
+
+    /// How much of `spans` falls between `start` and `end`, counting time covered by several spans once
+    pub fn total_overlap(
+        spans: impl IntoIterator<Item = Span>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> TimeDelta {
+        let mut spans: Vec<Span> = spans
+            .into_iter()
+            .map(|span| Span {
+                start: span.start.max(start),
+                end: span.end.min(end),
+            })
+            .filter(|span| span.start < span.end)
+            .collect();
+        spans.sort_by_key(|span| span.start);
+        let mut total = TimeDelta::zero();
+        let mut counted_until = start;
+        for span in spans {
+            total += (span.end - span.start.max(counted_until)).max(TimeDelta::zero());
+            counted_until = counted_until.max(span.end);
+        }
+        total
+    }
+}
+
+/// Time in the ongoing session which probably wasn't spent on it, found by `State::stale_time`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StaleTime {
+    pub activity_id: ActivityId,
+    pub reason: StaleReason,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StaleReason {
+    /// The TUI was open but didn't run between `from` and `to`, most likely because the computer was asleep
+    Gap,
+    /// The session ran for longer than `MAX_SESSION`, and `from` is when it reached that length
+    LongSession,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StaleResolution {
+    /// Count the stale time towards the session anyway
+    Keep,
+    /// End the session at the start of the stale time
+    Trim,
+    /// Leave the stale time out of the session, which keeps running
+    Discard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StaleTimeDecision {
+    pub activity_id: ActivityId,
+    /// When the decision was made
+    pub time: DateTime<Utc>,
+    pub reason: StaleReason,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub resolution: StaleResolution,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Correction {
+    pub activity_id: ActivityId,
+    pub time: DateTime<Utc>,
+    pub minutes: i64,
+}
+
+/// A snapshot of a finished day, taken when the day is refreshed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayRecord {
+    pub date: NaiveDate,
+    pub activities: Vec<ArchivedActivity>,
+    pub log: Vec<LogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedActivity {
+    pub id: ActivityId,
+    pub name: String,
+    pub target_minutes: usize,
+    pub acheived_minutes: usize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ActivityId(usize);
+impl ActivityId {
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn to_raw(self) -> usize {
+        self.0
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_raw(raw: usize) -> Self {
+        Self(raw)
+    }
+}
 impl Display for ActivityId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "[{id}]", id = self.0)
     }
 }
 
-mod todos_and_buckets {
-    use serde::{Deserialize, Serialize};
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    pub struct TodoItemOld {
-        pub item: String,
-        pub bucket: Option<String>,
+    fn minutes_ago(minutes: i64) -> DateTime<Utc> {
+        Utc::now() - TimeDelta::minutes(minutes)
+    }
+
+    /// A state with a session on an activity which started `minutes` ago, without the TUI watching it
+    fn state_with_session(minutes: i64) -> (State, ActivityId) {
+        let mut state = State::empty();
+        let id = state.add_activity("reading".to_string(), 60);
+        state.current = Some(CurrentActionInfo::new(id, minutes_ago(minutes), None));
+        (state, id)
+    }
+
+    #[test]
+    fn only_gaps_seen_by_the_tui_are_stale() {
+        // started from the command line an hour ago, and never seen by the TUI
+        let (mut state, id) = state_with_session(60);
+        assert_eq!(state.stale_time(Utc::now()), None);
+
+        let seen = minutes_ago(30);
+        state.mark_seen(seen);
+        let now = Utc::now();
+        assert_eq!(
+            state.stale_time(now),
+            Some(StaleTime {
+                activity_id: id,
+                reason: StaleReason::Gap,
+                from: seen,
+                to: now,
+            })
+        );
+
+        // closing the TUI means nobody is watching the session anymore
+        state.forget_seen();
+        assert_eq!(state.stale_time(now), None);
+
+        state.mark_seen(seen);
+        state.pause().unwrap();
+        assert_eq!(state.stale_time(now), None);
+    }
+
+    #[test]
+    fn long_sessions_are_stale_after_max_session() {
+        let (state, id) = state_with_session(5 * 60);
+        let start_time = state.current.as_ref().unwrap().start_time;
+        let stale = state.stale_time(Utc::now()).unwrap();
+        assert_eq!(stale.activity_id, id);
+        assert_eq!(stale.reason, StaleReason::LongSession);
+        assert_eq!(stale.from, start_time + MAX_SESSION);
+    }
+
+    fn resolve_gap(resolution: StaleResolution) -> (State, ActivityId) {
+        let (mut state, id) = state_with_session(60);
+        state.mark_seen(minutes_ago(30));
+        let stale = state.stale_time(Utc::now()).unwrap();
+        state.resolve_stale_time(stale, resolution);
+        assert!(matches!(
+            state.log.last(),
+            Some(LogEntry::StaleTime(decision)) if decision.resolution == resolution
+        ));
+        assert_eq!(state.stale_time(Utc::now()), None);
+        (state, id)
+    }
+
+    #[test]
+    fn kept_stale_time_counts() {
+        let (state, id) = resolve_gap(StaleResolution::Keep);
+        assert_eq!(state.current_id(), Some(id));
+        assert_eq!(state.current_task_minutes(), Some(60));
+    }
+
+    #[test]
+    fn trimmed_sessions_end_where_the_stale_time_starts() {
+        let (state, id) = resolve_gap(StaleResolution::Trim);
+        assert_eq!(state.current_id(), None);
+        assert_eq!(state.acheived_minutes(id), 30);
+    }
+
+    #[test]
+    fn discarded_stale_time_is_left_out_of_the_session() {
+        let (state, id) = resolve_gap(StaleResolution::Discard);
+        assert_eq!(state.current_id(), Some(id));
+        assert_eq!(state.current_task_minutes(), Some(30));
+    }
+
+    fn session(id: ActivityId, from_minutes_ago: i64, to_minutes_ago: i64) -> LogEntry {
+        LogEntry::Session(Session {
+            activity_id: id,
+            start_time: minutes_ago(from_minutes_ago),
+            end_time: minutes_ago(to_minutes_ago),
+            pomo_minutes: None,
+            extended_minutes: 0,
+            overridden: false,
+            split: false,
+            excluded: vec![],
+        })
+    }
+
+    #[test]
+    fn acheived_time_is_summed_from_the_log() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        let spanish = state.add_activity("spanish".to_string(), 30);
+        state.log.push(session(reading, 90, 60));
+        state.log.push(session(spanish, 50, 40));
+        state.add_time(reading, 15).unwrap();
+        assert_eq!(state.acheived_minutes(reading), 45);
+        assert_eq!(state.acheived_minutes(spanish), 10);
+
+        // overwriting logs the difference, rather than changing what was logged
+        state.overwrite_time(reading, 20).unwrap();
+        assert_eq!(state.acheived_minutes(reading), 20);
+        assert!(matches!(
+            state.log.last(),
+            Some(LogEntry::Correction(correction)) if correction.minutes == -25
+        ));
+        assert_eq!(state.log.len(), 4);
+
+        state.start_activity(spanish).unwrap();
+        state.current.as_mut().unwrap().start_time = minutes_ago(5);
+        state.end_activity(false).unwrap();
+        assert_eq!(state.acheived_minutes(spanish), 15);
+        assert_eq!(state.add_time(ActivityId(99), 5), Err(()));
+    }
+
+    #[test]
+    fn refreshing_archives_every_day_since_the_stored_one() {
+        let mut state = State::empty();
+        let today = state.today();
+        state.date = today - TimeDelta::days(3);
+        let reading = state.add_activity("reading".to_string(), 60);
+        state.log.push(session(reading, 90, 60));
+        state.refresh();
+
+        assert_eq!(state.date(), today);
+        assert!(state.log.is_empty());
+        let history: Vec<&DayRecord> = state.history().collect();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].date, today - TimeDelta::days(3));
+        assert_eq!(history[0].log.len(), 1);
+        assert_eq!(history[0].activities[0].acheived_minutes, 30);
+        assert_eq!(history[0].activities[0].target_minutes, 60);
+        // timetrack wasn't opened on the days in between
+        for (day, archived) in history[1..].iter().zip(1..) {
+            assert_eq!(day.date, today - TimeDelta::days(3 - archived));
+            assert!(day.log.is_empty());
+            assert_eq!(day.activities[0].acheived_minutes, 0);
+        }
+    }
+
+    #[test]
+    fn future_dates_are_moved_back_without_archiving() {
+        let mut state = State::empty();
+        let today = state.today();
+        state.date = today + TimeDelta::days(1);
+        let reading = state.add_activity("reading".to_string(), 60);
+        state.log.push(session(reading, 90, 60));
+        state.refresh();
+
+        assert_eq!(state.date(), today);
+        assert_eq!(state.history().count(), 0);
+        assert_eq!(state.acheived_minutes(reading), 30);
+    }
+
+    #[test]
+    fn raising_the_day_start_keeps_the_stored_day() {
+        let mut state = State::empty();
+        let stored = state.date();
+        let reading = state.add_activity("reading".to_string(), 60);
+        state.log.push(session(reading, 10, 0));
+        // the day now starts a minute from now, so it is still the previous day
+        state.set_day_start((Local::now() + TimeDelta::minutes(1)).time());
+        if state.today() == stored {
+            // a minute from now is past midnight, so the day start is earlier than now after all
+            return;
+        }
+        state.refresh();
+
+        assert_eq!(state.date(), stored - TimeDelta::days(1));
+        assert_eq!(state.history().count(), 0);
+        assert_eq!(state.acheived_minutes(reading), 10);
+    }
+
+    #[test]
+    fn every_fourth_break_is_long() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 180);
+        state.start_activity_pomo(reading, Some(25)).unwrap();
+        let mut breaks = vec![];
+        for _ in 0..5 {
+            state.current.as_mut().unwrap().start_time = minutes_ago(30);
+            let pomodoro_break = state.finish_work_block().unwrap();
+            breaks.push((pomodoro_break.long, pomodoro_break.minutes));
+            state.end_break(Some(reading));
+        }
+        assert_eq!(
+            breaks,
+            vec![(false, 5), (false, 5), (false, 5), (true, 15), (false, 5)]
+        );
+        // each work block ended when its timer was up, rather than when it was finished
+        assert_eq!(state.acheived_minutes(reading), 5 * 25);
+    }
+
+    #[test]
+    fn starting_a_session_during_a_break_logs_the_break() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 180);
+        let spanish = state.add_activity("spanish".to_string(), 30);
+        state.start_activity_pomo(reading, Some(25)).unwrap();
+        state.current.as_mut().unwrap().start_time = minutes_ago(27);
+        state.finish_work_block().unwrap();
+        let cycle = state.pomodoro.as_mut().unwrap();
+        cycle.on_break.as_mut().unwrap().start_time = minutes_ago(2);
+
+        state.start_activity(spanish).unwrap();
+        assert!(state.pomodoro_break().is_none());
+        assert!(matches!(
+            state.log.last(),
+            Some(LogEntry::Break(logged)) if logged.activity_id == reading && logged.end_time - logged.start_time >= TimeDelta::minutes(2)
+        ));
+        assert_eq!(state.current_id(), Some(spanish));
+    }
+
+    #[test]
+    fn paused_time_is_left_out_of_the_session() {
+        let (mut state, id) = state_with_session(30);
+        state.pause().unwrap();
+        assert_eq!(state.pause(), Err(PauseError::AlreadyPaused));
+        state.current.as_mut().unwrap().paused_at = Some(minutes_ago(20));
+        // the ongoing pause already doesn't count
+        assert_eq!(state.current_task_minutes(), Some(10));
+        state.resume().unwrap();
+        assert_eq!(state.resume(), Err(PauseError::NotPaused));
+        assert_eq!(state.current_task_minutes(), Some(10));
+
+        state.end_activity(false).unwrap();
+        assert!(matches!(
+            state.log.last(),
+            Some(LogEntry::Session(session)) if session.excluded.len() == 1
+        ));
+        assert_eq!(state.acheived_minutes(id), 10);
+        assert_eq!(state.pause(), Err(PauseError::NoCurrentActivity));
+    }
+
+    #[test]
+    fn extending_a_pomodoro_moves_its_timer() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        state.start_activity_pomo(reading, Some(25)).unwrap();
+        let start_time = minutes_ago(30);
+        state.current.as_mut().unwrap().start_time = start_time;
+        assert_eq!(
+            state.pomodoro_time_up(),
+            Some(start_time + TimeDelta::minutes(25))
+        );
+
+        state.extend_pomodoro(10).unwrap();
+        assert_eq!(state.pomo_minutes(), Some(35));
+        assert_eq!(state.pomodoro_time_up(), None);
+        let current = state.current.as_ref().unwrap();
+        assert_eq!(
+            current.timer_end(start_time + TimeDelta::minutes(40)),
+            Some(start_time + TimeDelta::minutes(35))
+        );
+
+        state.end_activity(true).unwrap();
+        assert_eq!(state.extend_pomodoro(5), Err(()));
+        assert!(matches!(
+            state.log.last(),
+            Some(LogEntry::Session(session)) if session.extended_minutes == 10 && session.overridden
+        ));
+    }
+
+    #[test]
+    fn sessions_running_at_the_end_of_the_day_are_split() {
+        let mut state = State::empty();
+        let today = state.today();
+        state.date = today - TimeDelta::days(1);
+        let reading = state.add_activity("reading".to_string(), 60);
+        let day_end = day::start_of(today, state.day_start);
+        state.current = Some(CurrentActionInfo::new(
+            reading,
+            day_end - TimeDelta::minutes(30),
+            None,
+        ));
+        state.refresh();
+
+        let yesterday = state.history().last().unwrap();
+        assert!(matches!(
+            yesterday.log.as_slice(),
+            [LogEntry::Session(session)] if session.split && session.end_time == day_end
+        ));
+        assert_eq!(yesterday.activities[0].acheived_minutes, 30);
+        // the rest of the session counts towards today
+        let current = state.current.as_ref().unwrap();
+        assert_eq!(current.activity_id, reading);
+        assert_eq!(current.credited_start(), day_end);
     }
 
+    fn activity_ids(state: &State) -> Vec<ActivityId> {
+        state.activities().map(Activity::id).collect()
+    }
+
+    #[test]
+    fn deleting_an_activity_can_be_undone_and_redone() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        let spanish = state.add_activity("spanish".to_string(), 30);
+        state.delete(reading).unwrap();
+        // failed operations aren't recorded
+        assert_eq!(state.delete(reading), Err(DeletionError::InvalidId));
+
+        assert_eq!(state.undo(), Some("delete activity"));
+        assert_eq!(activity_ids(&state), vec![reading, spanish]);
+        assert_eq!(state.redo(), Some("delete activity"));
+        assert_eq!(activity_ids(&state), vec![spanish]);
+        assert_eq!(state.redo(), None);
+    }
+
+    #[test]
+    fn overwriting_time_can_be_undone_and_redone() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        state.add_time(reading, 10).unwrap();
+        state.overwrite_time(reading, 30).unwrap();
+        assert_eq!(state.undo(), Some("overwrite time"));
+        assert_eq!(state.acheived_minutes(reading), 10);
+        assert_eq!(state.undo(), Some("register time"));
+        assert_eq!(state.acheived_minutes(reading), 0);
+        state.redo();
+        assert_eq!(state.redo(), Some("overwrite time"));
+        assert_eq!(state.acheived_minutes(reading), 30);
+    }
+
+    #[test]
+    fn deleting_a_todo_can_be_undone_and_redone() {
+        let todos = |state: &State| -> Vec<String> {
+            state.buckets[0]
+                .todos()
+                .map(|todo| todo.item().to_string())
+                .collect()
+        };
+        let mut state = State::empty();
+        state
+            .add_todo(0, TodoItem::new("buy milk".to_string()))
+            .unwrap();
+        state.delete_todo(0, 0).unwrap();
+        assert!(todos(&state).is_empty());
+        assert_eq!(state.undo(), Some("delete todo"));
+        assert_eq!(todos(&state), vec!["buy milk"]);
+        assert_eq!(state.redo(), Some("delete todo"));
+        assert!(todos(&state).is_empty());
+    }
+
+    #[test]
+    fn automatic_changes_cant_be_undone() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        state.start_activity_pomo(reading, Some(25)).unwrap();
+        state.current.as_mut().unwrap().start_time = minutes_ago(30);
+        state.automatic(State::finish_work_block).unwrap();
+        assert!(state.pomodoro_break().is_some());
+        // undoing the start would bring back the finished session
+        assert_eq!(state.undo(), None);
+        assert_eq!(state.current_id(), None);
+
+        state.add_time(reading, 5).unwrap();
+        state.automatic(|state| state.end_break(None));
+        assert_eq!(state.undo(), None);
+        assert_eq!(state.acheived_minutes(reading), 30);
+    }
+
+    #[test]
+    fn skipping_a_recommendation_logs_the_skip() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        let spanish = state.add_activity("spanish".to_string(), 30);
+        state.skip_recommendation(reading, spanish, 25).unwrap();
+        assert_eq!(state.current_id(), Some(spanish));
+        assert_eq!(state.pomo_minutes(), Some(25));
+        assert!(matches!(
+            state.log.as_slice(),
+            [LogEntry::Skip(skip)] if skip.activity_id == reading && skip.chosen == spanish
+        ));
+        // skips don't credit any time
+        assert_eq!(state.acheived_minutes(reading), 0);
+
+        // nothing is logged if the other activity can't be started
+        assert_eq!(
+            state.skip_recommendation(reading, spanish, 25),
+            Err(StartActivityError::AlreadyOngoing)
+        );
+        assert_eq!(state.log.len(), 1);
+    }
+
+    #[test]
+    fn changing_the_target_keeps_the_schedule() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        let spanish = state.add_activity("spanish".to_string(), 30);
+        state.set_schedule(spanish, Schedule::Weekly(700)).unwrap();
+        let activity = |state: &State, id| {
+            let activity = state.activities.iter().find(|activity| activity.id == id);
+            activity.unwrap().clone()
+        };
+
+        state.set_target_minutes(reading, 45).unwrap();
+        assert_eq!(activity(&state, reading).today_target_minutes(), 45);
+
+        // only today's target changes for activities with another schedule
+        state.set_target_minutes(spanish, 45).unwrap();
+        let spanish_activity = activity(&state, spanish);
+        assert_eq!(spanish_activity.schedule(), Schedule::Weekly(700));
+        assert_eq!(spanish_activity.today_target_minutes(), 45);
+        assert_eq!(spanish_activity.target_minutes, 30);
+
+        state.set_daily_target(spanish, 20).unwrap();
+        let spanish_activity = activity(&state, spanish);
+        assert_eq!(spanish_activity.schedule(), Schedule::Daily);
+        assert_eq!(spanish_activity.today_target_minutes(), 20);
+    }
+}
+
+mod todos_and_buckets {
+    use serde::{Deserialize, Serialize};
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct TodoItem(String);
     impl TodoItem {
@@ -521,10 +2132,6 @@ mod todos_and_buckets {
             self.todos.iter()
         }
 
-        pub fn set_name(&mut self, name: String) {
-            self.name = name;
-        }
-
         pub fn todos_mut(&mut self) -> &mut Vec<TodoItem> {
             &mut self.todos
         }
@@ -0,0 +1,423 @@
+//! Charts of the time tracked over the last few days, from the archived days and today's log.
+
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, TimeDelta};
+use ratatui::{
+    Frame,
+    crossterm::event::{Event, KeyCode, KeyEvent},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    symbols::Marker,
+    text::Line,
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Chart, Dataset, GraphType, Paragraph, Sparkline,
+        Widget,
+    },
+};
+
+use crate::{
+    Window, WindowActionResult, instruction_line,
+    state::{Activity, State},
+    streaks::day_summaries,
+};
+
+/// How many days the average in the trend chart is taken over
+const AVERAGE_DAYS: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatsRange {
+    Week,
+    Month,
+    Quarter,
+}
+impl StatsRange {
+    fn days(self) -> usize {
+        match self {
+            StatsRange::Week => 7,
+            StatsRange::Month => 30,
+            StatsRange::Quarter => 90,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            StatsRange::Week => StatsRange::Month,
+            StatsRange::Month => StatsRange::Quarter,
+            StatsRange::Quarter => StatsRange::Week,
+        }
+    }
+}
+
+/// The time spent and the target on one day, for one activity or all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct DayTotal {
+    date: NaiveDate,
+    minutes: usize,
+    target_minutes: usize,
+}
+impl DayTotal {
+    fn met(&self) -> bool {
+        self.target_minutes > 0 && self.minutes >= self.target_minutes
+    }
+}
+
+/// The days of the range, oldest first, ending with the stored day
+fn range_dates(state: &State, range: StatsRange) -> Vec<NaiveDate> {
+    (0..range.days())
+        .rev()
+        .map(|days_ago| state.date() - TimeDelta::days(days_ago as i64))
+        .collect()
+}
+
+/// The time spent on every activity on each date, including activities which have been deleted since
+fn totals(state: &State, dates: &[NaiveDate]) -> Vec<DayTotal> {
+    let archived: HashMap<NaiveDate, DayTotal> = state
+        .history()
+        .map(|day| {
+            (
+                day.date,
+                DayTotal {
+                    date: day.date,
+                    minutes: day.activities.iter().map(|x| x.acheived_minutes).sum(),
+                    target_minutes: day.activities.iter().map(|x| x.target_minutes).sum(),
+                },
+            )
+        })
+        .collect();
+    dates
+        .iter()
+        .map(|date| {
+            if *date == state.date() {
+                DayTotal {
+                    date: *date,
+                    minutes: state
+                        .activities()
+                        .map(|activity| state.minutes_today(activity.id()))
+                        .sum(),
+                    target_minutes: state.activities().map(Activity::today_target_minutes).sum(),
+                }
+            } else {
+                archived.get(date).copied().unwrap_or(DayTotal {
+                    date: *date,
+                    ..Default::default()
+                })
+            }
+        })
+        .collect()
+}
+
+/// The time spent on one activity on each date
+fn activity_totals(state: &State, activity: &Activity, dates: &[NaiveDate]) -> Vec<DayTotal> {
+    let summaries: HashMap<NaiveDate, DayTotal> = day_summaries(state, activity)
+        .into_iter()
+        .map(|day| {
+            (
+                day.date,
+                DayTotal {
+                    date: day.date,
+                    minutes: day.acheived_minutes,
+                    target_minutes: day.target_minutes,
+                },
+            )
+        })
+        .collect();
+    dates
+        .iter()
+        .map(|date| {
+            summaries.get(date).copied().unwrap_or(DayTotal {
+                date: *date,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// The average of each value and the ones before it, up to `window` values in all
+fn rolling_average(values: &[usize], window: usize) -> Vec<f64> {
+    (0..values.len())
+        .map(|i| {
+            let start = (i + 1).saturating_sub(window);
+            let window = &values[start..=i];
+            window.iter().sum::<usize>() as f64 / window.len() as f64
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct StatsWindow {
+    range: StatsRange,
+    /// The activity the charts are limited to, by its position in the list, or every activity if `None`
+    filter: Option<usize>,
+}
+impl StatsWindow {
+    pub fn new() -> Self {
+        Self {
+            range: StatsRange::Week,
+            filter: None,
+        }
+    }
+}
+impl Window for StatsWindow {
+    fn draw(&self, state: &State, frame: &mut Frame, area: Rect) {
+        let [per_day_area, lower_area] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(area);
+        let [trend_area, targets_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(lower_area);
+
+        let dates = range_dates(state, self.range);
+        let filtered = self.filter.and_then(|i| state.activities().nth(i));
+        let days = match filtered {
+            Some(activity) => activity_totals(state, activity, &dates),
+            None => totals(state, &dates),
+        };
+        let activities: Vec<(&Activity, Vec<DayTotal>)> = state
+            .activities()
+            .filter(|activity| filtered.is_none_or(|filtered| filtered.id() == activity.id()))
+            .map(|activity| (activity, activity_totals(state, activity, &dates)))
+            .collect();
+        let filter_name = filtered.map_or("All Activities", Activity::name);
+
+        frame.render_widget(
+            &PerDayWidget {
+                days: &days,
+                title: format!(
+                    " Minutes per Day: {filter_name}, Last {} Days ",
+                    self.range.days()
+                ),
+            },
+            per_day_area,
+        );
+        frame.render_widget(&TrendWidget { days: &days }, trend_area);
+        frame.render_widget(
+            &TargetsWidget {
+                activities: &activities,
+            },
+            targets_area,
+        );
+    }
+
+    fn handle_event(&mut self, state: &mut State, event: &Event) -> WindowActionResult {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            match code {
+                KeyCode::Char('q') => return WindowActionResult::Exit,
+                KeyCode::Char('1') => return WindowActionResult::FirstWindow,
+                KeyCode::Char('2') => return WindowActionResult::SecondWindow,
+                KeyCode::Char('3') => return WindowActionResult::ThirdWindow,
+                KeyCode::Char('4') => return WindowActionResult::FourthWindow,
+                KeyCode::Char('5') => return WindowActionResult::FifthWindow,
+                KeyCode::Char('r') => self.range = self.range.next(),
+                KeyCode::Right => {
+                    self.filter = match self.filter {
+                        None if state.activities_count() > 0 => Some(0),
+                        Some(i) if i + 1 < state.activities_count() => Some(i + 1),
+                        _ => None,
+                    };
+                }
+                KeyCode::Left => {
+                    self.filter = match self.filter {
+                        None => state.activities_count().checked_sub(1),
+                        Some(0) => None,
+                        Some(i) => Some(i - 1),
+                    };
+                }
+                _ => (),
+            }
+        }
+        WindowActionResult::Continue
+    }
+}
+
+struct PerDayWidget<'a> {
+    days: &'a [DayTotal],
+    title: String,
+}
+impl<'a> Widget for &PerDayWidget<'a> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let block = Block::bordered()
+            .title(self.title.as_str())
+            .title_bottom(instruction_line(vec![
+                ("Change Range", "R"),
+                ("Previous Activity", "Left"),
+                ("Next Activity", "Right"),
+            ]));
+        let width = (block.inner(area).width as usize / self.days.len().max(1)).max(1) as u16;
+        let gap = if width > 2 { 1 } else { 0 };
+        let bar_width = width - gap;
+        let bars: Vec<Bar> = self
+            .days
+            .iter()
+            .map(|day| {
+                let bar = Bar::default()
+                    .value(day.minutes as u64)
+                    .style(if day.met() { Color::Green } else { Color::Blue });
+                // narrow bars have no room for their labels
+                let bar = if bar_width >= 6 {
+                    bar.label(Line::from(day.date.format("%a %d").to_string()))
+                } else if bar_width >= 2 {
+                    bar.label(Line::from(day.date.format("%d").to_string()))
+                } else {
+                    bar
+                };
+                if bar_width >= 3 {
+                    bar
+                } else {
+                    bar.text_value(String::new())
+                }
+            })
+            .collect();
+        BarChart::default()
+            .block(block)
+            .data(BarGroup::default().bars(&bars))
+            .bar_width(bar_width)
+            .bar_gap(gap)
+            .render(area, buf);
+    }
+}
+
+/// The time spent each day, and its average over the last week
+struct TrendWidget<'a> {
+    days: &'a [DayTotal],
+}
+impl<'a> Widget for &TrendWidget<'a> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let minutes: Vec<usize> = self.days.iter().map(|day| day.minutes).collect();
+        let daily: Vec<(f64, f64)> = minutes
+            .iter()
+            .enumerate()
+            .map(|(i, minutes)| (i as f64, *minutes as f64))
+            .collect();
+        let average: Vec<(f64, f64)> = rolling_average(&minutes, AVERAGE_DAYS)
+            .into_iter()
+            .enumerate()
+            .map(|(i, average)| (i as f64, average))
+            .collect();
+        let max = minutes.iter().copied().max().unwrap_or(0).max(60) as f64;
+        let (first, last) = match (self.days.first(), self.days.last()) {
+            (Some(first), Some(last)) => (
+                first.date.format("%b %d").to_string(),
+                last.date.format("%b %d").to_string(),
+            ),
+            _ => (String::new(), String::new()),
+        };
+        Chart::new(vec![
+            Dataset::default()
+                .name("Daily")
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Cyan))
+                .data(&daily),
+            Dataset::default()
+                .name(format!("{AVERAGE_DAYS}-day average"))
+                .marker(Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::Yellow))
+                .data(&average),
+        ])
+        .block(Block::bordered().title(" Focus Time Trend "))
+        .x_axis(
+            Axis::default()
+                .bounds([0.0, (self.days.len().max(2) - 1) as f64])
+                .labels(vec![first, last])
+                .style(Style::default().dark_gray()),
+        )
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, max])
+                .labels(vec![
+                    "0".to_string(),
+                    format!("{}min", (max / 2.0).round()),
+                    format!("{max}min"),
+                ])
+                .style(Style::default().dark_gray()),
+        )
+        .render(area, buf);
+    }
+}
+
+/// The time spent on each activity over the range against its targets, with a sparkline of its days
+struct TargetsWidget<'a> {
+    activities: &'a [(&'a Activity, Vec<DayTotal>)],
+}
+impl<'a> Widget for &TargetsWidget<'a> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let block = Block::bordered().title(" Target vs Actual ");
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if self.activities.is_empty() {
+            Paragraph::new("No activities".dark_gray().italic()).render(inner, buf);
+            return;
+        }
+
+        let descriptions: Vec<Line> = self
+            .activities
+            .iter()
+            .map(|(activity, days)| {
+                let minutes: usize = days.iter().map(|day| day.minutes).sum();
+                let target_minutes: usize = days.iter().map(|day| day.target_minutes).sum();
+                let description = format!(
+                    "{} {minutes}/{target_minutes}min {}%",
+                    activity.name(),
+                    minutes * 100 / target_minutes.max(1)
+                );
+                if minutes >= target_minutes {
+                    Line::from(description.green())
+                } else {
+                    Line::from(description)
+                }
+            })
+            .collect();
+        let description_width = descriptions
+            .iter()
+            .map(Line::width)
+            .max()
+            .unwrap_or(0)
+            .min(inner.width as usize / 2) as u16;
+        let rows =
+            Layout::vertical(vec![Constraint::Length(1); self.activities.len()]).split(inner);
+        for ((description, (_, days)), row) in descriptions
+            .into_iter()
+            .zip(self.activities.iter())
+            .zip(rows.iter())
+        {
+            let [description_area, sparkline_area] = Layout::horizontal([
+                Constraint::Length(description_width + 1),
+                Constraint::Fill(1),
+            ])
+            .areas(*row);
+            Paragraph::new(description).render(description_area, buf);
+            Sparkline::default()
+                .data(
+                    days.iter()
+                        .map(|day| day.minutes as u64)
+                        .collect::<Vec<_>>(),
+                )
+                .style(Style::default().fg(Color::Cyan))
+                .render(sparkline_area, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_averages_start_with_fewer_values() {
+        assert_eq!(
+            rolling_average(&[10, 20, 30, 40], 2),
+            vec![10.0, 15.0, 25.0, 35.0]
+        );
+        assert_eq!(rolling_average(&[], 7), Vec::<f64>::new());
+    }
+}
@@ -0,0 +1,314 @@
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::Result;
+use color_eyre::eyre::eyre;
+
+use crate::migrations::{self, MigrationError};
+use crate::state::StateBuilder;
+
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+/// The minimum age of the newest backup before a save makes another one,
+/// so that frequent saves, like scripted ones, don't push every older backup out within seconds
+const BACKUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Somewhere the state can be persisted between runs
+pub trait Storage: std::fmt::Debug {
+    /// The file holding the state, which the lock file and backups are named after
+    fn path(&self) -> &Path;
+
+    /// Reads the stored state, creating an empty store first if there isn't one yet.
+    /// Nothing is written when `read_only` is true.
+    fn load(&self, read_only: bool) -> Result<StateBuilder>;
+
+    fn save(&self, state: &StateBuilder) -> Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Json,
+    Sqlite,
+}
+impl StorageKind {
+    /// Reads the kind of storage to use from `TIMETRACK_STORAGE`, defaulting to JSON
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("TIMETRACK_STORAGE").as_deref() {
+            Err(_) | Ok("json") => Ok(StorageKind::Json),
+            Ok("sqlite") => Ok(StorageKind::Sqlite),
+            Ok(other) => Err(eyre!(
+                "unknown TIMETRACK_STORAGE \"{other}\", expected \"json\" or \"sqlite\""
+            )),
+        }
+    }
+
+    pub fn default_file_name(self) -> &'static str {
+        match self {
+            StorageKind::Json => "state.json",
+            StorageKind::Sqlite => "state.sqlite3",
+        }
+    }
+}
+
+pub fn open(kind: StorageKind, path: PathBuf, backup_count: usize) -> Result<Rc<dyn Storage>> {
+    match kind {
+        StorageKind::Json => Ok(Rc::new(JsonStorage { path, backup_count })),
+        #[cfg(feature = "sqlite")]
+        StorageKind::Sqlite => Ok(Rc::new(sqlite::SqliteStorage::new(path, backup_count))),
+        #[cfg(not(feature = "sqlite"))]
+        StorageKind::Sqlite => Err(eyre!(
+            "timetrack was built without SQLite support; rebuild it with the `sqlite` feature"
+        )),
+    }
+}
+
+/// Stores the whole state as a single JSON file
+#[derive(Debug)]
+pub struct JsonStorage {
+    path: PathBuf,
+    backup_count: usize,
+}
+impl Storage for JsonStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self, read_only: bool) -> Result<StateBuilder> {
+        let path = &self.path;
+        if read_only {
+            return if path.exists() {
+                read_state_file(path)
+            } else {
+                parse_state("{}")
+            };
+        }
+        if !path.exists() {
+            write_atomic(path, b"{}")?;
+            return parse_state("{}");
+        }
+        match read_state_file(path) {
+            Ok(stored_state) => Ok(stored_state),
+            // falling back to an older backup would throw away whatever the newer version stored
+            Err(error) if error.downcast_ref::<MigrationError>().is_some() => Err(error),
+            Err(error) => match read_newest_valid_backup(path, self.backup_count) {
+                Some(stored_state) => {
+                    quarantine(path)?;
+                    Ok(stored_state)
+                }
+                None => Err(error),
+            },
+        }
+    }
+
+    fn save(&self, state: &StateBuilder) -> Result<()> {
+        back_up(&self.path, self.backup_count)?;
+        write_atomic(&self.path, serde_json::to_string(state)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path`, flushes it to disk,
+/// then renames it over `path`, so that `path` is never left half-written
+fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp_path = sibling_path(path, "tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp_path, path)?;
+    // the rename itself is only durable once the directory has been flushed
+    if let Some(dir) = path.parent().and_then(|dir| File::open(dir).ok()) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Reads a state file, upgrading it to the current schema if it was written by an older version
+fn read_state_file(path: &Path) -> Result<StateBuilder> {
+    parse_state(&fs::read_to_string(path)?)
+}
+
+fn parse_state(contents: &str) -> Result<StateBuilder> {
+    let stored_state = serde_json::from_str(contents)?;
+    Ok(serde_json::from_value(migrations::migrate(stored_state)?)?)
+}
+
+/// An advisory lock on the stored state, which is released when this is dropped
+#[derive(Debug)]
+pub struct StateLock {
+    _file: File,
+}
+
+/// Locks the state file at `path` for this process,
+/// returning `None` if another process already holds the lock
+pub fn try_lock(path: &Path) -> io::Result<Option<StateLock>> {
+    // the JSON state file is replaced on every save, so the lock is taken on a sibling file instead
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(sibling_path(path, "lock"))?;
+    match file.try_lock() {
+        Ok(()) => Ok(Some(StateLock { _file: file })),
+        Err(TryLockError::WouldBlock) => Ok(None),
+        Err(TryLockError::Error(error)) => Err(error),
+    }
+}
+
+/// `state.json` -> `state.json.<n>`, where 1 is the newest backup
+fn backup_path(path: &Path, n: usize) -> PathBuf {
+    sibling_path(path, &n.to_string())
+}
+
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+/// Copies the file at `path` into the newest backup slot,
+/// shifting older backups along and dropping the oldest
+fn rotate_backups(path: &Path, count: usize) -> io::Result<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    for n in (1..count).rev() {
+        let from = backup_path(path, n);
+        if from.exists() {
+            fs::rename(from, backup_path(path, n + 1))?;
+        }
+    }
+    let newest = backup_path(path, 1);
+    write_atomic(&newest, &fs::read(path)?)
+}
+
+/// Rotates the backups before the file at `path` is replaced, unless the newest backup is recent
+fn back_up(path: &Path, count: usize) -> io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let newest_age = fs::metadata(backup_path(path, 1))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+    if newest_age.is_some_and(|age| age < BACKUP_INTERVAL) {
+        return Ok(());
+    }
+    rotate_backups(path, count)
+}
+
+/// Reads the newest backup which can still be parsed
+fn read_newest_valid_backup(path: &Path, count: usize) -> Option<StateBuilder> {
+    (1..=count).find_map(|n| read_state_file(&backup_path(path, n)).ok())
+}
+
+/// Moves an unreadable state file out of the way so it can be inspected later
+fn quarantine(path: &Path) -> io::Result<()> {
+    fs::rename(path, sibling_path(path, "corrupt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    /// An empty directory for a test to put its files in
+    pub(super) fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("timetrack-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    fn state_on(date: &str) -> String {
+        format!(
+            r#"{{"schema_version": {}, "date": "{date}"}}"#,
+            migrations::CURRENT_SCHEMA_VERSION
+        )
+    }
+
+    #[test]
+    fn atomic_writes_replace_the_file() {
+        let path = test_dir("write-atomic").join("state.json");
+        write_atomic(&path, b"old").unwrap();
+        write_atomic(&path, b"new").unwrap();
+        assert_eq!(read(&path), "new");
+        assert!(!sibling_path(&path, "tmp").exists());
+    }
+
+    #[test]
+    fn rotating_drops_the_oldest_backup() {
+        let path = test_dir("rotate").join("state.json");
+        for contents in ["1", "2", "3"] {
+            fs::write(&path, contents).unwrap();
+            rotate_backups(&path, 2).unwrap();
+        }
+        assert_eq!(read(&backup_path(&path, 1)), "3");
+        assert_eq!(read(&backup_path(&path, 2)), "2");
+        assert!(!backup_path(&path, 3).exists());
+    }
+
+    #[test]
+    fn saves_only_back_up_once_the_newest_backup_is_old() {
+        let path = test_dir("back-up").join("state.json");
+        let storage = JsonStorage {
+            path: path.clone(),
+            backup_count: 3,
+        };
+        let state = storage.load(false).unwrap();
+        storage.save(&state).unwrap();
+        storage.save(&state).unwrap();
+        assert!(backup_path(&path, 1).exists());
+        assert!(!backup_path(&path, 2).exists());
+
+        File::options()
+            .write(true)
+            .open(backup_path(&path, 1))
+            .unwrap()
+            .set_modified(SystemTime::now() - 2 * BACKUP_INTERVAL)
+            .unwrap();
+        storage.save(&state).unwrap();
+        assert!(backup_path(&path, 2).exists());
+    }
+
+    #[test]
+    fn unreadable_files_fall_back_to_the_newest_valid_backup() {
+        let path = test_dir("fall-back").join("state.json");
+        fs::write(&path, "not json").unwrap();
+        fs::write(backup_path(&path, 1), "{").unwrap();
+        fs::write(backup_path(&path, 2), state_on("2025-06-02")).unwrap();
+        fs::write(backup_path(&path, 3), state_on("2025-06-01")).unwrap();
+        let storage = JsonStorage {
+            path: path.clone(),
+            backup_count: 3,
+        };
+        let state = storage.load(false).unwrap();
+        assert_eq!(state.date, NaiveDate::from_ymd_opt(2025, 6, 2));
+        // the broken file is kept for inspection
+        assert_eq!(read(&sibling_path(&path, "corrupt")), "not json");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn unreadable_files_without_backups_fail_to_load() {
+        let path = test_dir("no-backup").join("state.json");
+        fs::write(&path, "not json").unwrap();
+        let storage = JsonStorage {
+            path: path.clone(),
+            backup_count: 3,
+        };
+        assert!(storage.load(false).is_err());
+        assert_eq!(read(&path), "not json");
+    }
+}
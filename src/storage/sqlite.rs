@@ -0,0 +1,365 @@
+use std::path::{Path, PathBuf};
+
+use color_eyre::Result;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Transaction, params};
+
+use super::{Storage, back_up};
+use serde_json::{Map, Value, json};
+
+use crate::migrations::{self, CURRENT_SCHEMA_VERSION};
+use crate::state::{LogEntry, StateBuilder};
+
+/// Stores the state in an SQLite database.
+///
+/// Archived days never change, so they are only written once,
+/// and a save only rewrites the current day, the activities and the todo list.
+/// Rows which hold structured values keep their full JSON in a `data` or `entry` column,
+/// next to plain columns which are useful for querying the database by hand.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    path: PathBuf,
+    backup_count: usize,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS activities (
+    id INTEGER PRIMARY KEY,
+    position INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    target_minutes INTEGER NOT NULL,
+    data TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS buckets (
+    position INTEGER PRIMARY KEY,
+    name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS todos (
+    bucket_position INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    item TEXT NOT NULL,
+    PRIMARY KEY (bucket_position, position)
+);
+CREATE TABLE IF NOT EXISTS days (
+    date TEXT PRIMARY KEY,
+    activities TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS sessions (
+    day TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    activity_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    start_time TEXT,
+    end_time TEXT,
+    minutes INTEGER NOT NULL,
+    entry TEXT NOT NULL,
+    PRIMARY KEY (day, position)
+);
+";
+
+impl SqliteStorage {
+    pub fn new(path: PathBuf, backup_count: usize) -> Self {
+        Self { path, backup_count }
+    }
+
+    fn open(&self) -> Result<Connection> {
+        let connection = Connection::open(&self.path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(connection)
+    }
+}
+impl Storage for SqliteStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self, read_only: bool) -> Result<StateBuilder> {
+        let connection = if read_only {
+            if !self.path.exists() {
+                return Ok(empty_state());
+            }
+            Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        } else {
+            self.open()?
+        };
+        read_state(&connection)
+    }
+
+    fn save(&self, state: &StateBuilder) -> Result<()> {
+        back_up(&self.path, self.backup_count)?;
+        let mut connection = self.open()?;
+        let transaction = connection.transaction()?;
+        write_state(&transaction, state)?;
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+fn empty_state() -> StateBuilder {
+    StateBuilder {
+        schema_version: CURRENT_SCHEMA_VERSION,
+        date: None,
+        activities: None,
+        log: None,
+        history: None,
+        next_activity_id: None,
+        current: None,
+        buckets: None,
+        pomodoro: None,
+        recommendation_strategy: None,
+    }
+}
+
+fn read_meta(connection: &Connection, key: &str) -> Result<Option<String>> {
+    Ok(connection
+        .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+            row.get(0)
+        })
+        .optional()?)
+}
+
+fn read_log(connection: &Connection, day: &str) -> Result<Vec<Value>> {
+    connection
+        .prepare("SELECT entry FROM sessions WHERE day = ?1 ORDER BY position")?
+        .query_map([day], |row| row.get::<_, String>(0))?
+        .map(|entry| Ok(serde_json::from_str(&entry?)?))
+        .collect()
+}
+
+/// Reads the rows back into the same JSON a `JsonStorage` would hold,
+/// so that databases written by older versions go through the same migrations
+fn read_state(connection: &Connection) -> Result<StateBuilder> {
+    let Some(schema_version) = read_meta(connection, "schema_version")? else {
+        // nothing has been saved yet
+        return Ok(empty_state());
+    };
+    let mut state = Map::new();
+    state.insert(
+        "schema_version".to_string(),
+        serde_json::from_str(&schema_version)?,
+    );
+
+    let date = read_meta(connection, "date")?;
+    if let Some(date) = &date {
+        state.insert("date".to_string(), Value::from(date.as_str()));
+        state.insert("log".to_string(), Value::from(read_log(connection, date)?));
+    }
+    for key in [
+        "next_activity_id",
+        "current",
+        "pomodoro",
+        "recommendation_strategy",
+    ] {
+        if let Some(value) = read_meta(connection, key)? {
+            state.insert(key.to_string(), serde_json::from_str(&value)?);
+        }
+    }
+
+    let activities = connection
+        .prepare("SELECT data FROM activities ORDER BY position")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .map(|data| Ok(serde_json::from_str(&data?)?))
+        .collect::<Result<Vec<Value>>>()?;
+    state.insert("activities".to_string(), Value::from(activities));
+    let buckets = connection
+        .prepare("SELECT position, name FROM buckets ORDER BY position")?
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .map(|bucket| {
+            let (position, name) = bucket?;
+            let todos = connection
+                .prepare("SELECT item FROM todos WHERE bucket_position = ?1 ORDER BY position")?
+                .query_map([position], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(json!({ "name": name, "todos": todos }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    state.insert("buckets".to_string(), Value::from(buckets));
+    let history = connection
+        .prepare("SELECT date, activities FROM days ORDER BY date")?
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+        .map(|day| {
+            let (date, activities) = day?;
+            Ok(json!({
+                "activities": serde_json::from_str::<Value>(&activities)?,
+                "log": read_log(connection, &date)?,
+                "date": date,
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    state.insert("history".to_string(), Value::from(history));
+
+    Ok(serde_json::from_value(migrations::migrate(
+        Value::Object(state),
+    )?)?)
+}
+
+fn write_log(transaction: &Transaction, day: &str, log: &[LogEntry]) -> Result<()> {
+    transaction.execute("DELETE FROM sessions WHERE day = ?1", [day])?;
+    let mut insert = transaction.prepare(
+        "INSERT INTO sessions (day, position, activity_id, kind, start_time, end_time, minutes, entry)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?;
+    for (position, entry) in log.iter().enumerate() {
+        let (kind, start_time, end_time) = match entry {
+            LogEntry::Session(session) => (
+                "session",
+                Some(session.start_time.to_rfc3339()),
+                Some(session.end_time.to_rfc3339()),
+            ),
+            LogEntry::Correction(correction) => {
+                ("correction", Some(correction.time.to_rfc3339()), None)
+            }
+            LogEntry::StaleTime(decision) => (
+                "stale_time",
+                Some(decision.from.to_rfc3339()),
+                Some(decision.to.to_rfc3339()),
+            ),
+            LogEntry::Break(pomodoro_break) => (
+                "break",
+                Some(pomodoro_break.start_time.to_rfc3339()),
+                Some(pomodoro_break.end_time.to_rfc3339()),
+            ),
+            LogEntry::Skip(skip) => ("skip", Some(skip.time.to_rfc3339()), None),
+        };
+        insert.execute(params![
+            day,
+            position,
+            entry.activity_id().to_raw(),
+            kind,
+            start_time,
+            end_time,
+            entry.minutes(),
+            serde_json::to_string(entry)?,
+        ])?;
+    }
+    Ok(())
+}
+
+fn write_state(transaction: &Transaction, state: &StateBuilder) -> Result<()> {
+    let mut set_meta =
+        transaction.prepare("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)")?;
+    set_meta.execute(["schema_version", &state.schema_version.to_string()])?;
+    if let Some(date) = state.date {
+        set_meta.execute(["date", &date.to_string()])?;
+    }
+    if let Some(next_activity_id) = state.next_activity_id {
+        set_meta.execute(["next_activity_id", &next_activity_id.to_string()])?;
+    }
+    match &state.current {
+        Some(current) => {
+            set_meta.execute(["current", &serde_json::to_string(current)?])?;
+        }
+        None => {
+            transaction.execute("DELETE FROM meta WHERE key = 'current'", [])?;
+        }
+    }
+    if let Some(strategy) = state.recommendation_strategy {
+        set_meta.execute([
+            "recommendation_strategy",
+            &serde_json::to_string(&strategy)?,
+        ])?;
+    }
+    match &state.pomodoro {
+        Some(pomodoro) => {
+            set_meta.execute(["pomodoro", &serde_json::to_string(pomodoro)?])?;
+        }
+        None => {
+            transaction.execute("DELETE FROM meta WHERE key = 'pomodoro'", [])?;
+        }
+    }
+
+    transaction.execute("DELETE FROM activities", [])?;
+    let mut insert_activity = transaction.prepare(
+        "INSERT INTO activities (id, position, name, target_minutes, data) VALUES (?1, ?2, ?3, ?4, ?5)",
+    )?;
+    for (position, activity) in state.activities.iter().flatten().enumerate() {
+        insert_activity.execute(params![
+            activity.id().to_raw(),
+            position,
+            activity.name(),
+            activity.target_minutes(),
+            serde_json::to_string(activity)?,
+        ])?;
+    }
+
+    transaction.execute("DELETE FROM buckets", [])?;
+    transaction.execute("DELETE FROM todos", [])?;
+    let mut insert_bucket =
+        transaction.prepare("INSERT INTO buckets (position, name) VALUES (?1, ?2)")?;
+    let mut insert_todo = transaction
+        .prepare("INSERT INTO todos (bucket_position, position, item) VALUES (?1, ?2, ?3)")?;
+    for (bucket_position, bucket) in state.buckets.iter().flatten().enumerate() {
+        insert_bucket.execute(params![bucket_position, bucket.name()])?;
+        for (position, todo) in bucket.todos().enumerate() {
+            insert_todo.execute(params![bucket_position, position, todo.item()])?;
+        }
+    }
+
+    let mut insert_day =
+        transaction.prepare("INSERT OR IGNORE INTO days (date, activities) VALUES (?1, ?2)")?;
+    for day in state.history.iter().flatten() {
+        let date = day.date.to_string();
+        if insert_day.execute([&date, &serde_json::to_string(&day.activities)?])? > 0 {
+            write_log(transaction, &date, &day.log)?;
+        }
+    }
+
+    if let Some(date) = state.date {
+        write_log(
+            transaction,
+            &date.to_string(),
+            state.log.as_deref().unwrap_or_default(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::tests::test_dir;
+
+    fn fixture() -> Value {
+        serde_json::from_str(include_str!("../../fixtures/state_v5.json")).unwrap()
+    }
+
+    #[test]
+    fn saved_states_load_unchanged() {
+        let storage = SqliteStorage::new(test_dir("sqlite-round-trip").join("state.sqlite3"), 0);
+        let state: StateBuilder = serde_json::from_value(fixture()).unwrap();
+        storage.save(&state).unwrap();
+        let loaded = storage.load(true).unwrap();
+        assert_eq!(serde_json::to_value(loaded).unwrap(), fixture());
+    }
+
+    #[test]
+    fn older_databases_are_migrated() {
+        let storage = SqliteStorage::new(test_dir("sqlite-migrate").join("state.sqlite3"), 0);
+        let state: StateBuilder = serde_json::from_value(fixture()).unwrap();
+        storage.save(&state).unwrap();
+        storage
+            .open()
+            .unwrap()
+            .execute(
+                "UPDATE meta SET value = '3' WHERE key = 'schema_version'",
+                [],
+            )
+            .unwrap();
+
+        let mut older = fixture();
+        older["schema_version"] = Value::from(3);
+        let loaded = storage.load(false).unwrap();
+        assert_eq!(loaded.schema_version, CURRENT_SCHEMA_VERSION);
+        assert_eq!(
+            serde_json::to_value(loaded).unwrap(),
+            migrations::migrate(older).unwrap()
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Streaks of days on which an activity's target was met, and a calendar heatmap of them.
+//! Days with no target, like the days off of a schedule, neither count towards a streak nor break it.
+
+use std::collections::HashMap;
+
+use chrono::{Datelike, NaiveDate, TimeDelta, Weekday};
+use ratatui::{
+    Frame,
+    crossterm::event::{Event, KeyCode, KeyEvent},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, Paragraph, Widget},
+};
+
+use crate::{
+    Window, WindowActionResult, instruction_line,
+    schedule::week_start,
+    state::{Activity, State},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayOutcome {
+    Met,
+    /// The target wasn't met, or the activity wasn't tracked that day
+    Missed,
+    /// The activity had no target that day
+    Rest,
+    /// Today, while there is still time to meet the target
+    Pending,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DaySummary {
+    pub date: NaiveDate,
+    pub acheived_minutes: usize,
+    pub target_minutes: usize,
+    /// False if there is no record of the activity on this day
+    pub tracked: bool,
+    pub outcome: DayOutcome,
+}
+impl DaySummary {
+    /// How much of the target was met, from 0 to 1
+    fn ratio(&self) -> f64 {
+        (self.acheived_minutes as f64 / self.target_minutes.max(1) as f64).min(1.0)
+    }
+}
+
+/// Summarises every day from the first archived day up to the stored day, oldest first
+pub fn day_summaries(state: &State, activity: &Activity) -> Vec<DaySummary> {
+    let archived: HashMap<NaiveDate, (usize, usize)> = state
+        .history()
+        .filter_map(|day| {
+            day.activities
+                .iter()
+                .find(|archived| archived.id == activity.id())
+                .map(|archived| {
+                    (
+                        day.date,
+                        (archived.acheived_minutes, archived.target_minutes),
+                    )
+                })
+        })
+        .collect();
+    let first = state.history().next().map_or(state.date(), |day| day.date);
+    first
+        .iter_days()
+        .take_while(|date| *date <= state.date())
+        .map(|date| {
+            if date == state.date() {
+                let acheived_minutes = state.minutes_today(activity.id());
+                let target_minutes = activity.today_target_minutes();
+                DaySummary {
+                    date,
+                    acheived_minutes,
+                    target_minutes,
+                    tracked: true,
+                    outcome: if target_minutes == 0 {
+                        DayOutcome::Rest
+                    } else if acheived_minutes >= target_minutes {
+                        DayOutcome::Met
+                    } else {
+                        DayOutcome::Pending
+                    },
+                }
+            } else if let Some((acheived_minutes, target_minutes)) = archived.get(&date) {
+                DaySummary {
+                    date,
+                    acheived_minutes: *acheived_minutes,
+                    target_minutes: *target_minutes,
+                    tracked: true,
+                    outcome: if *target_minutes == 0 {
+                        DayOutcome::Rest
+                    } else if acheived_minutes >= target_minutes {
+                        DayOutcome::Met
+                    } else {
+                        DayOutcome::Missed
+                    },
+                }
+            } else {
+                // timetrack wasn't opened that day
+                DaySummary {
+                    date,
+                    acheived_minutes: 0,
+                    target_minutes: 0,
+                    tracked: false,
+                    outcome: if activity.schedule().is_day_off(date) {
+                        DayOutcome::Rest
+                    } else {
+                        DayOutcome::Missed
+                    },
+                }
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Streaks {
+    /// The days in a row up to today, which isn't broken by today until the day is over
+    pub current: usize,
+    pub longest: usize,
+}
+impl Streaks {
+    /// Counts the streaks in `outcomes`, which are in order of their days
+    pub fn of(outcomes: impl IntoIterator<Item = DayOutcome>) -> Self {
+        let mut streaks = Streaks::default();
+        for outcome in outcomes {
+            match outcome {
+                DayOutcome::Met => {
+                    streaks.current += 1;
+                    streaks.longest = streaks.longest.max(streaks.current);
+                }
+                DayOutcome::Missed => streaks.current = 0,
+                DayOutcome::Rest | DayOutcome::Pending => (),
+            }
+        }
+        streaks
+    }
+}
+
+#[derive(Debug)]
+pub struct StreaksWindow {
+    selected_activity: usize,
+}
+impl StreaksWindow {
+    pub fn new() -> Self {
+        Self {
+            selected_activity: 0,
+        }
+    }
+}
+impl Window for StreaksWindow {
+    fn draw(&self, state: &State, frame: &mut Frame, area: Rect) {
+        let [streaks_area, heatmap_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(area);
+        let summaries: Vec<(&Activity, Vec<DaySummary>)> = state
+            .activities()
+            .map(|activity| (activity, day_summaries(state, activity)))
+            .collect();
+
+        frame.render_widget(
+            &StreaksWidget {
+                activities: summaries
+                    .iter()
+                    .map(|(activity, days)| {
+                        (
+                            activity.name(),
+                            Streaks::of(days.iter().map(|day| day.outcome)),
+                        )
+                    })
+                    .collect(),
+                selected_activity: self.selected_activity,
+            },
+            streaks_area,
+        );
+        frame.render_widget(
+            &HeatmapWidget {
+                activity_name: summaries
+                    .get(self.selected_activity)
+                    .map(|(activity, _)| activity.name()),
+                days: summaries
+                    .get(self.selected_activity)
+                    .map(|(_, days)| days.as_slice())
+                    .unwrap_or_default(),
+                today: state.date(),
+            },
+            heatmap_area,
+        );
+    }
+
+    fn handle_event(&mut self, state: &mut State, event: &Event) -> WindowActionResult {
+        if let Event::Key(KeyEvent { code, .. }) = event {
+            match code {
+                KeyCode::Char('q') => return WindowActionResult::Exit,
+                KeyCode::Char('1') => return WindowActionResult::FirstWindow,
+                KeyCode::Char('2') => return WindowActionResult::SecondWindow,
+                KeyCode::Char('3') => return WindowActionResult::ThirdWindow,
+                KeyCode::Char('4') => return WindowActionResult::FourthWindow,
+                KeyCode::Char('5') => return WindowActionResult::FifthWindow,
+                KeyCode::Down => {
+                    self.selected_activity = (self.selected_activity + 1)
+                        .min(state.activities_count().saturating_sub(1));
+                }
+                KeyCode::Up => {
+                    self.selected_activity = self.selected_activity.saturating_sub(1);
+                }
+                _ => (),
+            }
+        }
+        WindowActionResult::Continue
+    }
+}
+
+struct StreaksWidget<'a> {
+    activities: Vec<(&'a str, Streaks)>,
+    selected_activity: usize,
+}
+impl<'a> Widget for &StreaksWidget<'a> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let max_name_length = self
+            .activities
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        List::new(
+            self.activities
+                .iter()
+                .enumerate()
+                .map(|(i, (name, streaks))| {
+                    let line = Line::from(vec![
+                        format!("{name:max_name_length$} ").into(),
+                        format!("{:>4}d", streaks.current).green(),
+                        format!(" best {}d", streaks.longest).dark_gray(),
+                    ]);
+                    if i == self.selected_activity {
+                        line.blue().bold()
+                    } else {
+                        line
+                    }
+                }),
+        )
+        .style(Color::Yellow)
+        .block(
+            Block::bordered()
+                .title(" Streaks ")
+                .title_bottom(instruction_line(vec![
+                    ("Scroll Up", "Up"),
+                    ("Scroll Down", "Down"),
+                ])),
+        )
+        .render(area, buf);
+    }
+}
+
+/// A calendar with a column for each week and a row for each day of the week, like GitHub's
+struct HeatmapWidget<'a> {
+    activity_name: Option<&'a str>,
+    days: &'a [DaySummary],
+    today: NaiveDate,
+}
+impl<'a> Widget for &HeatmapWidget<'a> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        const LABEL_WIDTH: u16 = 4;
+        const CELL_WIDTH: u16 = 2;
+        let block = Block::bordered()
+            .title(match self.activity_name {
+                Some(name) => format!(" {name} "),
+                None => " Heatmap ".to_string(),
+            })
+            .title_bottom(Line::from(vec![
+                " Less ".into(),
+                cell(Some(0.0)),
+                cell(Some(0.3)),
+                cell(Some(0.7)),
+                cell(Some(1.0)),
+                "More  ".into(),
+                "- ".dark_gray(),
+                "Day Off ".into(),
+            ]));
+        let inner = block.inner(area);
+        block.render(area, buf);
+        if self.activity_name.is_none() {
+            Paragraph::new("No activities".dark_gray().italic()).render(inner, buf);
+            return;
+        }
+
+        let weeks = (inner.width.saturating_sub(LABEL_WIDTH) / CELL_WIDTH).max(1) as i64;
+        let first_week = week_start(self.today) - TimeDelta::weeks(weeks - 1);
+        let days: HashMap<NaiveDate, &DaySummary> =
+            self.days.iter().map(|day| (day.date, day)).collect();
+
+        let mut months = " ".repeat(LABEL_WIDTH as usize);
+        let mut previous_month = None;
+        for week in 0..weeks {
+            let start = first_week + TimeDelta::weeks(week);
+            let column = (LABEL_WIDTH as i64 + week * CELL_WIDTH as i64) as usize;
+            // a month is labelled above the first week which starts in it, if the previous label leaves room
+            if previous_month != Some(start.month()) && months.len() <= column {
+                months.push_str(&" ".repeat(column - months.len()));
+                months.push_str(&start.format("%b").to_string());
+            }
+            previous_month = Some(start.month());
+        }
+        let mut lines = vec![Line::from(months.dark_gray())];
+        for weekday in 0..7 {
+            let mut spans = vec![Span::raw(format!(
+                "{:<width$}",
+                Weekday::try_from(weekday as u8).unwrap().to_string(),
+                width = LABEL_WIDTH as usize
+            ))];
+            for week in 0..weeks {
+                let date = first_week + TimeDelta::weeks(week) + TimeDelta::days(weekday);
+                spans.push(if date > self.today {
+                    Span::raw("  ")
+                } else {
+                    match days.get(&date) {
+                        Some(day) if day.outcome == DayOutcome::Rest => "- ".dark_gray(),
+                        Some(day) if day.tracked => cell(Some(day.ratio())),
+                        _ => cell(None),
+                    }
+                });
+            }
+            lines.push(Line::from(spans));
+        }
+        Paragraph::new(lines).render(inner, buf);
+    }
+}
+
+/// A day on the heatmap, which is greener the more of its target was met,
+/// and a dot if there is no record of the day
+fn cell(ratio: Option<f64>) -> Span<'static> {
+    match ratio {
+        None => "· ".dark_gray(),
+        Some(ratio) if ratio <= 0.0 => Span::styled("■ ", Style::default().fg(Color::DarkGray)),
+        Some(ratio) if ratio < 0.5 => Span::styled("■ ", Style::default().fg(Color::Indexed(22))),
+        Some(ratio) if ratio < 1.0 => Span::styled("■ ", Style::default().fg(Color::Indexed(28))),
+        Some(_) => Span::styled("■ ", Style::default().fg(Color::Indexed(40))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DayOutcome::*;
+
+    #[test]
+    fn missed_days_break_streaks() {
+        let streaks = Streaks::of([Met, Met, Met, Missed, Met]);
+        assert_eq!(
+            streaks,
+            Streaks {
+                current: 1,
+                longest: 3
+            }
+        );
+    }
+
+    #[test]
+    fn days_off_dont_break_streaks() {
+        let streaks = Streaks::of([Met, Rest, Met, Rest, Rest, Met]);
+        assert_eq!(
+            streaks,
+            Streaks {
+                current: 3,
+                longest: 3
+            }
+        );
+    }
+
+    #[test]
+    fn today_only_counts_once_it_is_met() {
+        assert_eq!(Streaks::of([Met, Met, Pending]).current, 2);
+        assert_eq!(Streaks::of([Met, Met, Met]).current, 3);
+        assert_eq!(Streaks::of([Met, Missed, Pending]).current, 0);
+    }
+}
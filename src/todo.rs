@@ -36,25 +36,12 @@ impl TodoWindow {
     }
 }
 impl TodoWindow {
-    fn get_selected_todo<'a>(&self, state: &'a State) -> Option<&'a TodoItem> {
-        self.get_selected_bucket(state)
-            .todos()
-            .nth(self.selected_todo)
-    }
-
     fn get_selected_bucket<'a>(&self, state: &'a State) -> &'a Bucket {
         state
             .get_buckets()
             .nth(self.selected_bucket)
             .expect("self.selected_bucket should be a valid bucket index")
     }
-
-    fn get_selected_bucket_mut<'a>(&self, state: &'a mut State) -> &'a mut Bucket {
-        state
-            .get_buckets_mut()
-            .nth(self.selected_bucket)
-            .expect("self.selected_bucket should be a valid bucket index")
-    }
 }
 impl Window for TodoWindow {
     fn draw(&self, state: &State, frame: &mut Frame, area: Rect) {
@@ -103,6 +90,7 @@ impl Window for TodoWindow {
         );
     }
 
+    #[allow(clippy::single_match, clippy::collapsible_match)]
     fn handle_event(&mut self, state: &mut State, event: &Event) -> WindowActionResult {
         use KeyCode::*;
         use TodoWidget::*;
@@ -124,8 +112,12 @@ impl Window for TodoWindow {
                             }
                         }
                         (Enter, TodoInput, _) => {
-                            let bucket = self.get_selected_bucket_mut(state);
-                            bucket.push_todo(TodoItem::new(self.todo_input.value().to_string()));
+                            state
+                                .add_todo(
+                                    self.selected_bucket,
+                                    TodoItem::new(self.todo_input.value().to_string()),
+                                )
+                                .expect("self.selected_bucket should be a valid bucket index");
                             self.todo_input.reset();
                         }
                         (Enter, BucketInput, _) => {
@@ -136,23 +128,16 @@ impl Window for TodoWindow {
                             self.bucket_input.reset();
                         }
                         (Enter, Todos, _) => {
-                            if self.selected_todo < self.get_selected_bucket(state).todos().count()
+                            if state
+                                .delete_todo(self.selected_bucket, self.selected_todo)
+                                .is_ok()
                             {
-                                let bucket = self.get_selected_bucket_mut(state);
-                                *bucket.todos_mut() = bucket
-                                    .todos()
-                                    .map(TodoItem::clone)
-                                    .take(self.selected_todo)
-                                    .chain(
-                                        bucket
-                                            .todos()
-                                            .map(TodoItem::clone)
-                                            .skip(self.selected_todo + 1),
-                                    )
-                                    .collect();
-                                self.selected_todo = self
-                                    .selected_todo
-                                    .min(bucket.todos().count().saturating_sub(1));
+                                self.selected_todo = self.selected_todo.min(
+                                    self.get_selected_bucket(state)
+                                        .todos()
+                                        .count()
+                                        .saturating_sub(1),
+                                );
                             }
                         }
                         (Enter, Buckets, _) => {
@@ -184,19 +169,27 @@ impl Window for TodoWindow {
                             self.selected_todo = 0;
                         }
                         (Left, Todos, _) => {
-                            if self.selected_todo > 0 {
-                                self.get_selected_bucket_mut(state)
-                                    .todos_mut()
-                                    .swap(self.selected_todo, self.selected_todo - 1);
+                            if self.selected_todo > 0
+                                && state
+                                    .swap_todos(
+                                        self.selected_bucket,
+                                        self.selected_todo,
+                                        self.selected_todo - 1,
+                                    )
+                                    .is_ok()
+                            {
                                 self.selected_todo -= 1;
                             }
                         }
                         (Right, Todos, _) => {
-                            let bucket_size = self.get_selected_bucket(state).todos().count();
-                            if bucket_size > 1 && self.selected_todo < bucket_size - 1 {
-                                self.get_selected_bucket_mut(state)
-                                    .todos_mut()
-                                    .swap(self.selected_todo, self.selected_todo + 1);
+                            if state
+                                .swap_todos(
+                                    self.selected_bucket,
+                                    self.selected_todo,
+                                    self.selected_todo + 1,
+                                )
+                                .is_ok()
+                            {
                                 self.selected_todo += 1;
                             }
                         }
@@ -240,18 +233,9 @@ impl Window for TodoWindow {
                                 selected_todo,
                             },
                         ) => {
-                            let todo_item = state
-                                .get_buckets_mut()
-                                .nth(selected_bucket)
-                                .expect("should be able to get source bucket of move")
-                                .todos_mut()
-                                .remove(selected_todo);
                             state
-                                .get_buckets_mut()
-                                .nth(self.selected_bucket)
-                                .expect("should be able to get destination bucket for move")
-                                .todos_mut()
-                                .push(todo_item);
+                                .move_todo(selected_bucket, selected_todo, self.selected_bucket)
+                                .expect("should be able to move todo between buckets");
                             self.bucket_widget_purpose = BucketWidgetPurpose::Browse;
                             self.focused_widget = TodoWidget::Todos;
                         }
@@ -264,6 +248,12 @@ impl Window for TodoWindow {
                         (Char('3'), Todos | Buckets, _) => {
                             return WindowActionResult::ThirdWindow;
                         }
+                        (Char('4'), Todos | Buckets, _) => {
+                            return WindowActionResult::FourthWindow;
+                        }
+                        (Char('5'), Todos | Buckets, _) => {
+                            return WindowActionResult::FifthWindow;
+                        }
                         (_, TodoInput, _) => {
                             self.todo_input.handle_event(event);
                         }
@@ -1,3 +1,4 @@
+use chrono::Utc;
 use ratatui::{
     Frame,
     crossterm::event::{Event, KeyCode, KeyEvent},
@@ -12,48 +13,79 @@ use crate::{
     Window, WindowActionResult,
     input_widget::InputWidget,
     instruction_line,
+    pomodoro::TimeUpAction,
+    recommend::{Candidate, Context, Recommendation, recommend},
     state::{Activity, ActivityId, State},
 };
 
+/// How many recommendations the Up Next panel shows
+const UP_NEXT_LENGTH: usize = 3;
+
 pub enum FindRecommendedActionError {
     NoMoreTasks,
     Ongoing,
     OngoingCompleted,
 }
 
+/// Ranks the activities with time left today, best first, with the reason for each one's place.
+/// The list is never empty.
 pub fn find_recommended_action(
     current_state: &State,
-) -> Result<&Activity, FindRecommendedActionError> {
+) -> Result<Vec<Recommendation<'_>>, FindRecommendedActionError> {
     if let Some(current_task) = current_state.current_activity() {
-        if current_task.acheived_minutes()
-            + current_state
-                .current_session_duration()
-                .unwrap()
-                .num_minutes()
-                .max(0) as usize
-            >= current_task.target_minutes()
+        if current_state.acheived_minutes(current_task.id())
+            + current_state.current_task_minutes().unwrap()
+            >= current_task.today_target_minutes()
         {
             Err(FindRecommendedActionError::OngoingCompleted)
         } else {
             Err(FindRecommendedActionError::Ongoing)
         }
-    } else if let Some(activity) = current_state
-        .activities()
-        .filter(|x| x.target_minutes() > x.acheived_minutes())
-        .reduce(|a, b| {
-            if a.acheived_minutes() < b.acheived_minutes() {
-                a
-            } else {
-                b
-            }
-        })
-    {
-        Ok(activity)
     } else {
-        Err(FindRecommendedActionError::NoMoreTasks)
+        let recommendations = rank_activities(current_state);
+        if recommendations.is_empty() {
+            Err(FindRecommendedActionError::NoMoreTasks)
+        } else {
+            Ok(recommendations)
+        }
     }
 }
 
+/// Ranks the activities with time left today, other than the ongoing one, best first.
+/// Unlike `find_recommended_action`, this also ranks them during a session, to show what comes after it.
+fn rank_activities(current_state: &State) -> Vec<Recommendation<'_>> {
+    let candidates: Vec<Candidate> = current_state
+        .activities()
+        .enumerate()
+        .filter(|(_, activity)| current_state.current_id() != Some(activity.id()))
+        .map(|(position, activity)| Candidate {
+            activity,
+            position,
+            acheived_minutes: current_state.acheived_minutes(activity.id()),
+            last_worked: current_state.last_worked_time(activity.id()),
+        })
+        .filter(|x| x.remaining_minutes() > 0)
+        .collect();
+    let context = Context {
+        last_worked: current_state
+            .last_worked_activity()
+            .and_then(|id| current_state.activities().position(|x| x.id() == id)),
+        seed: current_state.recommendation_seed(),
+    };
+    recommend(
+        current_state.recommendation_strategy().strategy(),
+        &candidates,
+        &context,
+    )
+}
+
+/// The activity at the top of `find_recommended_action`'s ranking
+pub fn recommended_activity(
+    current_state: &State,
+) -> Result<&Activity, FindRecommendedActionError> {
+    find_recommended_action(current_state).map(|ranking| ranking[0].candidate.activity)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum TrackWindowWidget {
     Activities,
@@ -69,6 +101,8 @@ enum TimerInputPurpose {
     RegisterActivity,
     ChangeTarget,
     StartPomodoro,
+    /// Starts a pomodoro on the second recommendation, logging the first one as skipped
+    StartSecondChoice,
 }
 
 #[derive(Debug)]
@@ -92,17 +126,22 @@ impl TrackWindow {
 }
 impl Window for TrackWindow {
     fn draw(&self, state: &State, frame: &mut Frame, area: Rect) {
+        use TrackWindowWidget::*;
         let [
             activities_area,
             text_input_area,
             timer_input_area,
             ongoing_area,
+            up_next_area,
         ] = {
             let [upper_area, lower_area] =
                 Layout::vertical([Constraint::Min(3), Constraint::Length(3)]).areas(area);
-            let [activities_area, ongoing_area] =
+            let [activities_area, side_area] =
                 Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)])
                     .areas(upper_area);
+            let [ongoing_area, up_next_area] =
+                Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .areas(side_area);
             let [text_input_area, timer_input_area] =
                 Layout::horizontal([Constraint::Percentage(70), Constraint::Min(6)])
                     .areas(lower_area);
@@ -111,13 +150,14 @@ impl Window for TrackWindow {
                 text_input_area,
                 timer_input_area,
                 ongoing_area,
+                up_next_area,
             ]
         };
 
         frame.render_widget(
             &ActivitiesWidget {
                 state,
-                is_focused: self.focused_widget == TrackWindowWidget::Activities,
+                is_focused: self.focused_widget == Activities,
                 selected_activity: self.selected_activity,
             },
             activities_area,
@@ -134,16 +174,33 @@ impl Window for TrackWindow {
                     PomodoroInfo {
                         acheived_time,
                         remaining_time: total_minutes.saturating_sub(acheived_time),
+                        block: state.pomodoro_cycle().map(|cycle| cycle.completed + 1),
+                        time_up: state.pomodoro_time_up().is_some(),
+                        blocks: state.pomodoro_settings().long_break_every,
                     }
                 }),
-                is_focused: self.focused_widget == TrackWindowWidget::Ongoing,
+                pomodoro_break: state.pomodoro_break().map(|pomodoro_break| BreakInfo {
+                    remaining_time: (pomodoro_break.end_time() - Utc::now())
+                        .num_minutes()
+                        .max(0) as usize,
+                    long: pomodoro_break.long,
+                }),
+                is_focused: self.focused_widget == Ongoing,
                 state,
             },
             ongoing_area,
         );
+        frame.render_widget(
+            &UpNextWidget {
+                recommendations: rank_activities(state),
+                ongoing: state.current_id().is_some(),
+                show_instructions: matches!(self.focused_widget, Activities | Ongoing),
+            },
+            up_next_area,
+        );
         frame.render_widget(
             &InputWidget {
-                is_focused: self.focused_widget == TrackWindowWidget::TextInput,
+                is_focused: self.focused_widget == TextInput,
                 input: &self.text_input,
                 title: "Add Activity",
             },
@@ -152,7 +209,7 @@ impl Window for TrackWindow {
         frame.render_widget(
             &TimerInputWidget {
                 value: self.timer_input,
-                is_focused: self.focused_widget == TrackWindowWidget::TimerInput,
+                is_focused: self.focused_widget == TimerInput,
                 purpose: self.timer_input_purpose,
                 selected_activity_name: self.selected_activity_name(state),
             },
@@ -211,19 +268,27 @@ impl Window for TrackWindow {
                 }
                 TimerInputPurpose::ChangeTarget => {
                     if let Some(activity_id) = self.selected_activity_id(state) {
-                        if let Some(activity) = state.get_by_id_mut(activity_id) {
-                            activity.set_target_minutes(self.timer_input);
-                        }
+                        let _ = state.set_target_minutes(activity_id, self.timer_input);
                     }
                     self.focused_widget = Activities;
                     self.timer_input_purpose = TimerInputPurpose::NewActivity;
                 }
                 TimerInputPurpose::StartPomodoro => {
-                    if let Ok(activity) = find_recommended_action(state) {
+                    if let Ok(activity) = recommended_activity(state) {
                         let id = activity.id();
                         let _ = state.start_activity_pomo(id, Some(self.timer_input));
                     }
                 }
+                TimerInputPurpose::StartSecondChoice => {
+                    if let Ok([first, second, ..]) = find_recommended_action(state).as_deref() {
+                        let (skipped, chosen) = (
+                            first.candidate.activity.id(),
+                            second.candidate.activity.id(),
+                        );
+                        let _ = state.skip_recommendation(skipped, chosen, self.timer_input);
+                    }
+                    self.timer_input_purpose = TimerInputPurpose::NewActivity;
+                }
             },
             Event::Key(KeyEvent {
                 code: KeyCode::Char('q'),
@@ -266,6 +331,16 @@ impl Window for TrackWindow {
                     }
                 }
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(' '),
+                ..
+            }) if self.focused_widget == Ongoing => {
+                if state.is_paused() {
+                    let _ = state.resume();
+                } else {
+                    let _ = state.pause();
+                }
+            }
             Event::Key(KeyEvent {
                 code: KeyCode::Backspace,
                 ..
@@ -293,6 +368,8 @@ impl Window for TrackWindow {
             }) if self.focused_widget == Ongoing => {
                 if state.pomo_minutes().is_some() {
                     let _ = state.end_activity(true);
+                } else if state.pomodoro_break().is_some() {
+                    state.end_break(None);
                 }
             }
             Event::Key(KeyEvent {
@@ -313,6 +390,18 @@ impl Window for TrackWindow {
             }) if self.focused_widget != TextInput => {
                 return WindowActionResult::ThirdWindow;
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('4'),
+                ..
+            }) if self.focused_widget != TextInput => {
+                return WindowActionResult::FourthWindow;
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('5'),
+                ..
+            }) if self.focused_widget != TextInput => {
+                return WindowActionResult::FifthWindow;
+            }
             Event::Key(KeyEvent {
                 code: KeyCode::Char('r'),
                 ..
@@ -335,10 +424,10 @@ impl Window for TrackWindow {
                 code: KeyCode::Char('p'),
                 ..
             }) if self.focused_widget == Activities || self.focused_widget == Ongoing => {
-                if let Ok(ideal_session_minutes) = find_recommended_action(state).map(|activity| {
+                if let Ok(ideal_session_minutes) = recommended_activity(state).map(|activity| {
                     activity
-                        .target_minutes()
-                        .saturating_sub(activity.acheived_minutes())
+                        .today_target_minutes()
+                        .saturating_sub(state.acheived_minutes(activity.id()))
                         .min(30)
                 }) {
                     self.focused_widget = TimerInput;
@@ -346,6 +435,16 @@ impl Window for TrackWindow {
                     self.timer_input_purpose = TimerInputPurpose::StartPomodoro;
                 }
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('n'),
+                ..
+            }) if self.focused_widget == Activities || self.focused_widget == Ongoing => {
+                if let Ok([_, second, ..]) = find_recommended_action(state).as_deref() {
+                    self.focused_widget = TimerInput;
+                    self.timer_input = second.candidate.remaining_minutes().min(30);
+                    self.timer_input_purpose = TimerInputPurpose::StartSecondChoice;
+                }
+            }
             Event::Key(KeyEvent {
                 code: KeyCode::Char('c'),
                 ..
@@ -355,6 +454,29 @@ impl Window for TrackWindow {
                     self.focused_widget = TimerInput;
                 }
             }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(key @ ('+' | '-')),
+                ..
+            }) if self.focused_widget == Activities => {
+                if let Some(activity) = self
+                    .selected_activity_id(state)
+                    .and_then(|id| state.activity_by_id(id))
+                {
+                    let (id, priority) = (activity.id(), activity.priority());
+                    let priority = if *key == '+' {
+                        priority + 1
+                    } else {
+                        priority.saturating_sub(1)
+                    };
+                    let _ = state.set_priority(id, priority);
+                }
+            }
+            Event::Key(KeyEvent {
+                code: KeyCode::Char('m'),
+                ..
+            }) if self.focused_widget == Activities => {
+                state.set_recommendation_strategy(state.recommendation_strategy().next());
+            }
             _ => {
                 if self.focused_widget == TextInput {
                     self.text_input.handle_event(event);
@@ -364,6 +486,10 @@ impl Window for TrackWindow {
 
         WindowActionResult::Continue
     }
+
+    fn is_typing(&self) -> bool {
+        self.focused_widget == TrackWindowWidget::TextInput
+    }
 }
 impl TrackWindow {
     fn selected_activity_id(&self, state: &State) -> Option<ActivityId> {
@@ -399,8 +525,20 @@ impl<'a> Widget for &ActivitiesWidget<'a> {
             ("Register Time", "R"),
             ("Overwrite Time", "O"),
             ("Start Pomodoro", "P"),
+            ("Take Second Choice", "N"),
             ("Change Target", "C"),
+            ("Priority", "+/-"),
+            ("Change Strategy", "M"),
         ]);
+        let recommendation = Line::from(match recommended_activity(self.state) {
+            Ok(activity) => format!(
+                " Next: {} ({}) ",
+                activity.name(),
+                self.state.recommendation_strategy().name()
+            ),
+            Err(_) => format!(" {} ", self.state.recommendation_strategy().name()),
+        })
+        .right_aligned();
         let max_name_length: usize = self
             .state
             .activities()
@@ -429,9 +567,12 @@ impl<'a> Widget for &ActivitiesWidget<'a> {
         .block(if self.is_focused {
             Block::bordered()
                 .title(" Activities ")
+                .title(recommendation)
                 .title_bottom(activities_instructions.centered())
         } else {
-            Block::bordered().title(" Activities ")
+            Block::bordered()
+                .title(" Activities ")
+                .title(recommendation)
         })
         .render(area, buf);
     }
@@ -440,6 +581,7 @@ impl<'a> Widget for &ActivitiesWidget<'a> {
 struct OngoingWidget<'a> {
     ongoing: Option<Activity>,
     pomodoro: Option<PomodoroInfo>,
+    pomodoro_break: Option<BreakInfo>,
     is_focused: bool,
     state: &'a State,
 }
@@ -457,21 +599,53 @@ impl<'a> Widget for &OngoingWidget<'a> {
             if let Some(PomodoroInfo {
                 acheived_time,
                 remaining_time,
+                block,
+                blocks,
+                time_up,
             }) = self.pomodoro
             {
-                Paragraph::new(vec![
-                    self.state.format_activity(ongoing, None),
-                    Line::from(format!(
-                        "Work for {r}min! Acheived {a} / {t} min",
-                        r = remaining_time,
-                        a = acheived_time,
-                        t = (acheived_time + remaining_time)
-                    )),
-                ])
-                .wrap(Wrap { trim: true })
+                if time_up {
+                    Paragraph::new(vec![
+                        self.state.format_activity(ongoing, None),
+                        Line::from(
+                            "Time's up! Keep going for a few more minutes, or finish the session."
+                                .yellow()
+                                .bold(),
+                        ),
+                    ])
+                    .wrap(Wrap { trim: true })
+                } else {
+                    Paragraph::new(vec![
+                        self.state.format_activity(ongoing, None),
+                        Line::from(format!(
+                            "{phase}Work for {r}min! Acheived {a} / {t} min",
+                            phase = block.map_or(String::new(), |block| format!(
+                                "Pomodoro {block}/{blocks}: "
+                            )),
+                            r = remaining_time,
+                            a = acheived_time,
+                            t = (acheived_time + remaining_time)
+                        )),
+                    ])
+                    .wrap(Wrap { trim: true })
+                }
             } else {
                 Paragraph::new(self.state.format_activity(ongoing, None))
             }
+        } else if let Some(BreakInfo {
+            remaining_time,
+            long,
+        }) = self.pomodoro_break
+        {
+            Paragraph::new(vec![
+                Line::from(
+                    if long { "Long break" } else { "Short break" }
+                        .green()
+                        .bold(),
+                ),
+                Line::from(format!("Rest for {remaining_time}min!")),
+            ])
+            .wrap(Wrap { trim: true })
         } else {
             Paragraph::new("No ongoing session".dark_gray().italic())
         }
@@ -480,9 +654,32 @@ impl<'a> Widget for &OngoingWidget<'a> {
                 .title(" Ongoing ")
                 .style(block_style)
                 .borders(Borders::all())
-                .title_bottom(if self.is_focused {
+                .title_bottom(if self.pomodoro.as_ref().is_some_and(|x| x.time_up) {
+                    // the time's up keys work from any widget but a text input, so they are shown even without focus
+                    let actions: Vec<(String, String)> = TimeUpAction::ALL
+                        .into_iter()
+                        .map(|action| (action.label(), action.keybind().to_uppercase().to_string()))
+                        .collect();
+                    instruction_line(
+                        actions
+                            .iter()
+                            .map(|(label, keybind)| (label.as_str(), keybind.as_str()))
+                            .collect(),
+                    )
+                } else if self.is_focused {
                     if self.ongoing.is_some() {
-                        instruction_line(vec![("End Pomodoro Session", "Backspace")])
+                        let pause = if self.state.is_paused() {
+                            ("Resume", "Space")
+                        } else {
+                            ("Pause", "Space")
+                        };
+                        if self.pomodoro.is_some() {
+                            instruction_line(vec![pause, ("End Pomodoro Session", "Backspace")])
+                        } else {
+                            instruction_line(vec![pause])
+                        }
+                    } else if self.pomodoro_break.is_some() {
+                        instruction_line(vec![("End Pomodoro Cycle", "Backspace")])
                     } else {
                         instruction_line(vec![("Start Pomodoro Session", "P")])
                     }
@@ -493,9 +690,71 @@ impl<'a> Widget for &OngoingWidget<'a> {
         .render(area, buf);
     }
 }
+/// The top few recommendations, and why each of them is ranked where it is
+struct UpNextWidget<'a> {
+    recommendations: Vec<Recommendation<'a>>,
+    /// True during a session, which leaves the ongoing activity out of the recommendations
+    ongoing: bool,
+    show_instructions: bool,
+}
+impl<'a> Widget for &UpNextWidget<'a> {
+    fn render(self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer)
+    where
+        Self: Sized,
+    {
+        let lines: Vec<Line> = if self.recommendations.is_empty() {
+            vec![Line::from(
+                if self.ongoing {
+                    "Every other target is reached"
+                } else {
+                    "Every target is reached"
+                }
+                .dark_gray()
+                .italic(),
+            )]
+        } else {
+            self.recommendations
+                .iter()
+                .take(UP_NEXT_LENGTH)
+                .enumerate()
+                .flat_map(|(i, recommendation)| {
+                    [
+                        Line::from(vec![
+                            format!("{}. ", i + 1).into(),
+                            recommendation.candidate.activity.name().bold(),
+                            format!(": {}", recommendation.reason).into(),
+                        ]),
+                        Line::from(recommendation.candidate.details().dark_gray()),
+                    ]
+                })
+                .collect()
+        };
+        Paragraph::new(lines)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title(" Up Next ").title_bottom(
+                // the second choice can only be taken between sessions
+                if self.show_instructions && !self.ongoing && self.recommendations.len() > 1 {
+                    instruction_line(vec![("Take Second Choice", "N")])
+                } else {
+                    Line::from(vec![])
+                },
+            ))
+            .render(area, buf);
+    }
+}
+
 struct PomodoroInfo {
     acheived_time: usize,
     remaining_time: usize,
+    /// Which work block of the cycle this is, counting from 1
+    block: Option<usize>,
+    blocks: usize,
+    /// True if the timer has run out, and the user is being asked whether to extend it
+    time_up: bool,
+}
+struct BreakInfo {
+    remaining_time: usize,
+    long: bool,
 }
 
 struct TimerInputWidget<'a> {
@@ -535,6 +794,9 @@ impl<'a> Widget for &TimerInputWidget<'a> {
                             self.selected_activity_name.unwrap_or_default()
                         ),
                         TimerInputPurpose::StartPomodoro => " Pomodoro Session Length ".to_string(),
+                        TimerInputPurpose::StartSecondChoice => {
+                            " Second Choice Session Length ".to_string()
+                        }
                     })
                     .title_bottom(if self.is_focused {
                         instruction_line(vec![
@@ -550,3 +812,31 @@ impl<'a> Widget for &TimerInputWidget<'a> {
             .render(area, buf);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked_ids(state: &State) -> Vec<ActivityId> {
+        rank_activities(state)
+            .iter()
+            .map(|recommendation| recommendation.candidate.activity.id())
+            .collect()
+    }
+
+    #[test]
+    fn up_next_ranks_the_other_activities_during_a_session() {
+        let mut state = State::empty();
+        let reading = state.add_activity("reading".to_string(), 60);
+        let spanish = state.add_activity("spanish".to_string(), 30);
+        state.add_activity("rest".to_string(), 0);
+        assert_eq!(ranked_ids(&state), vec![spanish, reading]);
+
+        state.start_activity(reading).unwrap();
+        assert!(matches!(
+            find_recommended_action(&state),
+            Err(FindRecommendedActionError::Ongoing)
+        ));
+        assert_eq!(ranked_ids(&state), vec![spanish]);
+    }
+}